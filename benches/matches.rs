@@ -0,0 +1,64 @@
+// benches/matches.rs
+
+use bpfjit_sys::{BpfJit, BpfJitBuilder};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// builds Ethernet/IPv4/TCP frames, with a quarter of them to or from port 443
+fn packets() -> Vec<Vec<u8>> {
+    (0..64u16)
+        .map(|n| {
+            let mut packet = vec![0u8; 54];
+            packet[12..14].copy_from_slice(&[0x08, 0x00]); // IPv4
+            packet[14] = 0x45; // version 4, 20-byte header
+            packet[16..18].copy_from_slice(&40u16.to_be_bytes()); // total length
+            packet[22] = 64; // ttl
+            packet[23] = 6; // TCP
+            let port: u16 = if n % 4 == 0 { 443 } else { 1024 + n };
+            packet[34..36].copy_from_slice(&(50000 + n).to_be_bytes());
+            packet[36..38].copy_from_slice(&port.to_be_bytes());
+            packet[46] = 0x50; // data offset
+            packet
+        })
+        .collect()
+}
+
+fn bench_matches(c: &mut Criterion) {
+    let packets = packets();
+    let jit = BpfJit::new("tcp port 443").unwrap();
+    let interpreter = BpfJitBuilder::new()
+        .jit(false)
+        .compile("tcp port 443")
+        .unwrap();
+
+    c.bench_function("matches", |b| {
+        b.iter(|| {
+            for packet in &packets {
+                black_box(jit.matches(black_box(packet)));
+            }
+        })
+    });
+
+    c.bench_function("matches (interpreter)", |b| {
+        b.iter(|| {
+            for packet in &packets {
+                black_box(interpreter.matches(black_box(packet)));
+            }
+        })
+    });
+
+    c.bench_function("Matcher::run", |b| {
+        let mut matcher = jit.matcher();
+        b.iter(|| {
+            for packet in &packets {
+                black_box(matcher.run(black_box(packet)));
+            }
+        })
+    });
+
+    c.bench_function("matches_many", |b| {
+        b.iter(|| black_box(jit.matches_many(packets.iter().map(|packet| &packet[..]))))
+    });
+}
+
+criterion_group!(benches, bench_matches);
+criterion_main!(benches);
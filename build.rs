@@ -1,13 +1,26 @@
 // build.rs
 
 use std::env;
+#[cfg(feature = "vendored")]
+use std::fs;
+#[cfg(feature = "vendored")]
+use std::path::Path;
 use std::path::PathBuf;
+#[cfg(feature = "vendored")]
+use std::process::Command;
 
-use cc;
+// the libpcap release built by the vendored feature, and the SHA-256 of its tarball
+#[cfg(feature = "vendored")]
+const LIBPCAP_VERSION: &str = "1.10.4";
+#[cfg(feature = "vendored")]
+const LIBPCAP_SHA256: &str = "ed19a0383fad72e3ad435fd239d7cd80d64916b87269550159d20e47160ebe5f";
 
 fn main() {
     let src = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap()).join("src");
 
+    #[cfg(feature = "vendored")]
+    let libpcap = build_libpcap();
+
     let mut cc = cc::Build::new();
     cc.warnings(false);
 
@@ -24,9 +37,121 @@ fn main() {
 
     cc.include(&src.join("sljit"));
     cc.include(&src.join("bpfjit"));
+    #[cfg(feature = "vendored")]
+    cc.include(libpcap.join("include")); // for pcap-bpf.h
 
     cc.file(src.join("sljit").join("sljitLir.c"));
     cc.file(src.join("bpfjit").join("bpfjit.c"));
 
     cc.compile("bpfjit");
+
+    if env::var_os("CARGO_FEATURE_PCAP").is_some() {
+        #[cfg(feature = "vendored")]
+        println!(
+            "cargo:rustc-link-search=native={}",
+            libpcap.join("lib").display()
+        );
+        if env::var_os("CARGO_FEATURE_STATIC").is_some() {
+            println!("cargo:rustc-link-lib=static=pcap");
+        } else {
+            println!("cargo:rustc-link-lib=pcap");
+        }
+    }
+}
+
+// builds libpcap from the source in $LIBPCAP_SRC (or the pinned release), with only the
+// capture backends that don't need any other libraries, and returns the directory it was
+// installed to
+#[cfg(feature = "vendored")]
+fn build_libpcap() -> PathBuf {
+    println!("cargo:rerun-if-env-changed=LIBPCAP_SRC");
+    let source = match env::var_os("LIBPCAP_SRC") {
+        Some(source) => PathBuf::from(source),
+        None => download_libpcap(),
+    };
+    if !source.join("CMakeLists.txt").exists() {
+        panic!(
+            "LIBPCAP_SRC is set to {}, which doesn't contain libpcap's source",
+            source.display()
+        );
+    }
+
+    cmake::Config::new(&source)
+        .define("BUILD_SHARED_LIBS", "OFF")
+        .define("BUILD_WITH_LIBNL", "OFF")
+        .define("DISABLE_BLUETOOTH", "ON")
+        .define("DISABLE_DBUS", "ON")
+        .define("DISABLE_DPDK", "ON")
+        .define("DISABLE_LINUX_USBMON", "ON")
+        .define("DISABLE_NETMAP", "ON")
+        .define("DISABLE_RDMA", "ON")
+        .define("ENABLE_REMOTE", "OFF")
+        .build()
+}
+
+// downloads the pinned libpcap release into OUT_DIR, checks its hash, and unpacks it
+#[cfg(feature = "vendored")]
+fn download_libpcap() -> PathBuf {
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    let source = out.join(format!("libpcap-{}", LIBPCAP_VERSION));
+    if source.join("CMakeLists.txt").exists() {
+        return source;
+    }
+
+    let tarball = out.join(format!("libpcap-{}.tar.gz", LIBPCAP_VERSION));
+    let url = format!(
+        "https://www.tcpdump.org/release/libpcap-{}.tar.gz",
+        LIBPCAP_VERSION
+    );
+    run(
+        Command::new("curl")
+            .args(&["--fail", "--silent", "--show-error", "--location"])
+            .arg("--output")
+            .arg(&tarball)
+            .arg(&url),
+        &format!(
+            "download {} (or set LIBPCAP_SRC to a copy of libpcap's source)",
+            url
+        ),
+    );
+
+    let hash = sha256(&tarball);
+    if hash != LIBPCAP_SHA256 {
+        let _ = fs::remove_file(&tarball);
+        panic!("{} has SHA-256 {}, expected {}", url, hash, LIBPCAP_SHA256);
+    }
+
+    run(
+        Command::new("tar")
+            .arg("-xzf")
+            .arg(&tarball)
+            .arg("-C")
+            .arg(&out),
+        "unpack libpcap",
+    );
+    source
+}
+
+// returns the SHA-256 of the file at `path`, in hex, with whichever of the usual tools exists
+#[cfg(feature = "vendored")]
+fn sha256(path: &Path) -> String {
+    let tools: &[(&str, &[&str])] = &[("sha256sum", &[]), ("shasum", &["-a", "256"])];
+    for (program, args) in tools {
+        if let Ok(output) = Command::new(program).args(*args).arg(path).output() {
+            if output.status.success() {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                return stdout.split_whitespace().next().unwrap_or("").to_string();
+            }
+        }
+    }
+    panic!("the vendored feature needs sha256sum or shasum to check libpcap's source");
+}
+
+#[cfg(feature = "vendored")]
+fn run(command: &mut Command, what: &str) {
+    match command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => panic!("failed to {}: {}", what, status),
+        Err(err) => panic!("failed to {}: {}", what, err),
+    }
 }
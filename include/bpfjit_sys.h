@@ -0,0 +1,58 @@
+/* include/bpfjit_sys.h */
+
+/*
+ * C API for bpfjit-sys, for linking the crate's staticlib into programs in other languages.
+ * Filters are compiled with libpcap and JIT'ed with bpfjit, as with `BpfJit::new_with_linktype()`.
+ */
+
+#ifndef BPFJIT_SYS_H
+#define BPFJIT_SYS_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* result codes, which are stable across releases */
+typedef enum bpfjit_error_t {
+    BPFJIT_OK = 0,
+    /* libpcap rejected the filter expression */
+    BPFJIT_COMPILE_ERR = 1,
+    /* sljit could not generate native code for the program */
+    BPFJIT_JIT_ERR = 2,
+    /* a pointer was NULL, or the filter expression isn't valid UTF-8 */
+    BPFJIT_INVALID_ARG = 3,
+    /* the program is empty, too large, or was rejected by bpf_validate() */
+    BPFJIT_INVALID_PROGRAM = 4,
+    /* the linked libpcap doesn't know the link-layer type */
+    BPFJIT_UNKNOWN_LINKTYPE = 5,
+    /* any other error, including a panic inside the library */
+    BPFJIT_OTHER_ERR = 99
+} bpfjit_error_t;
+
+/* a compiled filter, which is opaque to C */
+typedef struct bpfjit_sys_filter bpfjit_sys_filter_t;
+
+/*
+ * Compiles the NUL-terminated `filter` for packets of the link-layer type with the given DLT_*
+ * value, and stores the result in `*out`, to be freed with bpfjit_sys_free(); `*out` is set to
+ * NULL on error. Any DLT_* value the linked libpcap has a name for (see
+ * pcap_datalink_val_to_name()) is accepted.
+ */
+bpfjit_error_t bpfjit_sys_compile(const char *filter, int linktype, bpfjit_sys_filter_t **out);
+
+/*
+ * Returns 1 if the `len`-byte packet at `pkt` matches the filter, 0 if it doesn't, and -1 if
+ * matching it failed; `pkt` may be NULL if `len` is zero.
+ */
+int bpfjit_sys_matches(const bpfjit_sys_filter_t *jit, const unsigned char *pkt, size_t len);
+
+/* Frees a filter returned by bpfjit_sys_compile(); does nothing if `jit` is NULL. */
+void bpfjit_sys_free(bpfjit_sys_filter_t *jit);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* BPFJIT_SYS_H */
@@ -0,0 +1,136 @@
+// src/builder.rs
+
+use std::net::Ipv4Addr;
+
+use crate::{BpfJit, BpfJitError, LinkType, PCAP_NETMASK_UNKNOWN};
+
+/// Collects the options passed to libpcap when compiling a filter expression
+///
+/// The defaults compile for Ethernet with a snaplen of 65535, an unknown netmask, the optimizer
+/// and JIT enabled, no limit on the size of the program, and name resolution allowed, which is
+/// exactly what `BpfJit::new_ethernet()` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BpfJitBuilder {
+    pub(crate) linktype: LinkType,
+    pub(crate) snaplen: i32,
+    pub(crate) netmask: u32,
+    pub(crate) optimize: bool,
+    pub(crate) jit: bool,
+    pub(crate) max_instructions: Option<usize>,
+    pub(crate) resolve_names: bool,
+}
+
+impl BpfJitBuilder {
+    pub fn new() -> Self {
+        BpfJitBuilder {
+            linktype: LinkType::Ethernet,
+            snaplen: 65535,
+            netmask: PCAP_NETMASK_UNKNOWN,
+            optimize: true,
+            jit: true,
+            max_instructions: None,
+            resolve_names: true,
+        }
+    }
+
+    pub fn linktype(mut self, linktype: LinkType) -> Self {
+        self.linktype = linktype;
+        self
+    }
+
+    pub fn snaplen(mut self, snaplen: i32) -> Self {
+        self.snaplen = snaplen;
+        self
+    }
+
+    /// Sets the IPv4 netmask (in host byte order) of the capture interface
+    pub fn netmask(mut self, netmask: u32) -> Self {
+        self.netmask = netmask;
+        self
+    }
+
+    /// Sets the IPv4 netmask of the capture interface from an address, e.g. `255.255.255.0`
+    pub fn netmask_addr(self, netmask: Ipv4Addr) -> Self {
+        self.netmask(u32::from(netmask))
+    }
+
+    pub fn optimize(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Sets whether to JIT the compiled program; when disabled, or when sljit can't generate
+    /// code for the program, packets are matched with libpcap's `bpf_filter()` interpreter
+    pub fn jit(mut self, jit: bool) -> Self {
+        self.jit = jit;
+        self
+    }
+
+    /// Rejects compiled programs with more than `max_instructions` instructions with
+    /// `BpfJitError::ProgramTooLarge`, e.g. to protect services that compile user-supplied
+    /// filters
+    pub fn max_instructions(mut self, max_instructions: usize) -> Self {
+        self.max_instructions = Some(max_instructions);
+        self
+    }
+
+    /// Sets whether filters may name hosts and networks (e.g. `host example.com`), which
+    /// libpcap resolves while compiling, possibly with a blocking DNS query
+    ///
+    /// When disabled, such filters are rejected with `BpfJitError::Compile` before libpcap sees
+    /// them, so compiling never touches the network. Port and protocol names are still allowed.
+    pub fn resolve_names(mut self, resolve_names: bool) -> Self {
+        self.resolve_names = resolve_names;
+        self
+    }
+
+    pub fn compile(&self, filter: &str) -> Result<BpfJit, BpfJitError> {
+        BpfJit::compile(filter, self)
+    }
+}
+
+impl Default for BpfJitBuilder {
+    fn default() -> Self {
+        BpfJitBuilder::new()
+    }
+}
+
+/// The options passed to libpcap when compiling a filter expression, as a plain struct for
+/// callers that already have them from their configuration
+///
+/// The defaults are the same as `BpfJitBuilder`'s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompileOptions {
+    pub linktype: LinkType,
+    pub snaplen: i32,
+    /// the IPv4 netmask (in host byte order) of the capture interface
+    pub netmask: u32,
+    pub optimize: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        BpfJitBuilder::new().into()
+    }
+}
+
+impl From<BpfJitBuilder> for CompileOptions {
+    fn from(builder: BpfJitBuilder) -> Self {
+        CompileOptions {
+            linktype: builder.linktype,
+            snaplen: builder.snaplen,
+            netmask: builder.netmask,
+            optimize: builder.optimize,
+        }
+    }
+}
+
+impl From<CompileOptions> for BpfJitBuilder {
+    fn from(options: CompileOptions) -> Self {
+        BpfJitBuilder::new()
+            .linktype(options.linktype)
+            .snaplen(options.snaplen)
+            .netmask(options.netmask)
+            .optimize(options.optimize)
+    }
+}
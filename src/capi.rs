@@ -0,0 +1,135 @@
+// src/capi.rs
+
+// a C API, for using the crate as a static library from other languages, declared in
+// include/bpfjit_sys.h; panics are caught at the boundary rather than unwound into C
+
+#[cfg(feature = "pcap")]
+use std::ffi;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "pcap")]
+use std::ptr;
+use std::slice;
+
+#[cfg(feature = "pcap")]
+use crate::LinkType;
+use crate::{BpfJit, BpfJitError};
+
+/// Result codes returned by the C API, which are stable across releases
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum bpfjit_error_t {
+    BPFJIT_OK = 0,
+    /// libpcap rejected the filter expression
+    BPFJIT_COMPILE_ERR = 1,
+    /// sljit could not generate native code for the program
+    BPFJIT_JIT_ERR = 2,
+    /// a pointer was NULL, or the filter expression isn't valid UTF-8
+    BPFJIT_INVALID_ARG = 3,
+    /// the program is empty, too large, or was rejected by `bpf_validate()`
+    BPFJIT_INVALID_PROGRAM = 4,
+    /// the linked libpcap doesn't know the link-layer type
+    BPFJIT_UNKNOWN_LINKTYPE = 5,
+    /// any other error, including a panic inside the library
+    BPFJIT_OTHER_ERR = 99,
+}
+
+impl From<&BpfJitError> for bpfjit_error_t {
+    fn from(err: &BpfJitError) -> Self {
+        match err {
+            BpfJitError::Compile { .. } => bpfjit_error_t::BPFJIT_COMPILE_ERR,
+            BpfJitError::Jit => bpfjit_error_t::BPFJIT_JIT_ERR,
+            BpfJitError::NulByte(_) => bpfjit_error_t::BPFJIT_INVALID_ARG,
+            BpfJitError::InvalidProgram
+            | BpfJitError::EmptyProgram
+            | BpfJitError::ProgramTooLarge { .. } => bpfjit_error_t::BPFJIT_INVALID_PROGRAM,
+            BpfJitError::UnknownLinkType(_) => bpfjit_error_t::BPFJIT_UNKNOWN_LINKTYPE,
+            _ => bpfjit_error_t::BPFJIT_OTHER_ERR,
+        }
+    }
+}
+
+/// Compiles the NUL-terminated `filter` for packets of the link-layer type with the given
+/// `DLT_*` value, and stores the result in `*out`, to be freed with `bpfjit_sys_free()`
+///
+/// Any `DLT_*` value the linked libpcap has a name for is accepted. `*out` is set to NULL on
+/// error.
+///
+/// # Safety
+///
+/// `filter` must be NULL or point to a NUL-terminated string, and `out` must be NULL or valid
+/// for writes.
+#[cfg(feature = "pcap")]
+#[no_mangle]
+pub unsafe extern "C" fn bpfjit_sys_compile(
+    filter: *const libc::c_char,
+    linktype: libc::c_int,
+    out: *mut *mut BpfJit,
+) -> bpfjit_error_t {
+    if out.is_null() {
+        return bpfjit_error_t::BPFJIT_INVALID_ARG;
+    }
+    *out = ptr::null_mut();
+    if filter.is_null() {
+        return bpfjit_error_t::BPFJIT_INVALID_ARG;
+    }
+
+    let filter = match ffi::CStr::from_ptr(filter).to_str() {
+        Ok(filter) => filter,
+        Err(_) => return bpfjit_error_t::BPFJIT_INVALID_ARG,
+    };
+    // any DLT_* value the linked libpcap knows is accepted, whether or not `LinkType` has a
+    // variant for it
+    if crate::pcap_datalink_val_to_name(linktype).is_null() {
+        return bpfjit_error_t::BPFJIT_UNKNOWN_LINKTYPE;
+    }
+    let linktype = LinkType::from_dlt(linktype).unwrap_or(LinkType::Other(linktype));
+
+    match panic::catch_unwind(|| BpfJit::new_with_linktype(filter, linktype)) {
+        Ok(Ok(jit)) => {
+            *out = Box::into_raw(Box::new(jit));
+            bpfjit_error_t::BPFJIT_OK
+        }
+        Ok(Err(err)) => bpfjit_error_t::from(&err),
+        Err(_) => bpfjit_error_t::BPFJIT_OTHER_ERR,
+    }
+}
+
+/// Returns 1 if the `len`-byte packet at `pkt` matches the filter, 0 if it doesn't, and -1 if
+/// matching it panicked
+///
+/// # Safety
+///
+/// `jit` must have been returned by `bpfjit_sys_compile()` and not yet freed, and `pkt` must
+/// point to `len` readable bytes (or may be NULL if `len` is zero).
+#[no_mangle]
+pub unsafe extern "C" fn bpfjit_sys_matches(
+    jit: *const BpfJit,
+    pkt: *const u8,
+    len: libc::size_t,
+) -> libc::c_int {
+    let data = if pkt.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(pkt, len)
+    };
+    let jit = AssertUnwindSafe(&*jit);
+    match panic::catch_unwind(|| jit.matches(data)) {
+        Ok(matched) => matched as libc::c_int,
+        Err(_) => -1,
+    }
+}
+
+/// Frees a filter returned by `bpfjit_sys_compile()`; does nothing if `jit` is NULL
+///
+/// # Safety
+///
+/// `jit` must have been returned by `bpfjit_sys_compile()`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bpfjit_sys_free(jit: *mut BpfJit) {
+    if !jit.is_null() {
+        let jit = AssertUnwindSafe(Box::from_raw(jit));
+        // there is nothing to report a panic to, and the filter is gone either way
+        let _ = panic::catch_unwind(move || drop(jit));
+    }
+}
@@ -0,0 +1,170 @@
+// src/combine.rs
+
+use crate::opcode::*;
+use crate::{bpf_insn_t, BpfJitError};
+
+// what a rewritten program does instead of returning
+#[derive(Clone, Copy)]
+enum Action {
+    // returns as before
+    Keep,
+    // falls through to whatever follows the rewritten program
+    Continue,
+    // returns the given accept length instead
+    Return(u32),
+}
+
+fn stmt(code: u16, k: u32) -> bpf_insn_t {
+    bpf_insn_t {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+// the instructions that replace a `ret` with the given accept length (None for `ret a`), where
+// `end` is the offset from just after them to the end of the rewritten program
+fn replace_ret(k: Option<u32>, accept: Action, reject: Action, end: u32) -> Vec<bpf_insn_t> {
+    let emit = |action, ret: bpf_insn_t, end| match action {
+        Action::Keep => ret,
+        Action::Continue => stmt(BPF_JMP | BPF_JA, end),
+        Action::Return(k) => stmt(BPF_RET | BPF_K, k),
+    };
+
+    match k {
+        Some(0) => vec![emit(reject, stmt(BPF_RET | BPF_K, 0), end)],
+        Some(k) => vec![emit(accept, stmt(BPF_RET | BPF_K, k), end)],
+        None => vec![
+            bpf_insn_t {
+                code: BPF_JMP | BPF_JEQ | BPF_K,
+                jt: 0,
+                jf: 1,
+                k: 0,
+            },
+            emit(reject, stmt(BPF_RET | BPF_K, 0), end + 1),
+            emit(accept, stmt(BPF_RET | BPF_A, 0), end),
+        ],
+    }
+}
+
+// the accept length a `ret` returns, or None if it returns the accumulator
+fn ret_value(insn: &bpf_insn_t) -> Option<Option<u32>> {
+    match insn.code {
+        c if c == BPF_RET | BPF_K => Some(Some(insn.k)),
+        c if c == BPF_RET | BPF_A => Some(None),
+        _ => None,
+    }
+}
+
+// rewrites every `ret` in `insns` according to whether it accepts or rejects the packet, fixing
+// up the jumps around any instructions that grew
+fn rewrite(
+    insns: &[bpf_insn_t],
+    accept: Action,
+    reject: Action,
+) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    let mut starts = Vec::with_capacity(insns.len() + 1);
+    let mut len = 0;
+    for insn in insns {
+        starts.push(len);
+        len += match ret_value(insn) {
+            Some(k) => replace_ret(k, accept, reject, 0).len(),
+            None => 1,
+        };
+    }
+    starts.push(len);
+
+    let offset = |from: usize, to: usize| starts[to] - starts[from] - 1;
+    let mut result = Vec::with_capacity(len);
+    for (n, insn) in insns.iter().enumerate() {
+        if let Some(k) = ret_value(insn) {
+            let end = (len - starts[n + 1]) as u32;
+            result.extend(replace_ret(k, accept, reject, end));
+        } else if class(insn.code) == BPF_RET {
+            // libpcap never generates `ret x`, and neither bpf_filter() nor we support it
+            return Err(BpfJitError::InvalidProgram);
+        } else if insn.code == BPF_JMP | BPF_JA {
+            let to = n + 1 + insn.k as usize;
+            if to >= insns.len() {
+                return Err(BpfJitError::InvalidProgram);
+            }
+            result.push(stmt(insn.code, offset(n, to) as u32));
+        } else if class(insn.code) == BPF_JMP {
+            let jt = n + 1 + insn.jt as usize;
+            let jf = n + 1 + insn.jf as usize;
+            if jt >= insns.len() || jf >= insns.len() {
+                return Err(BpfJitError::InvalidProgram);
+            }
+            if offset(n, jt) > u8::MAX as usize || offset(n, jf) > u8::MAX as usize {
+                // the rewritten program is too long for the conditional jump to reach
+                return Err(BpfJitError::InvalidProgram);
+            }
+            result.push(bpf_insn_t {
+                jt: offset(n, jt) as u8,
+                jf: offset(n, jf) as u8,
+                ..*insn
+            });
+        } else {
+            result.push(*insn);
+        }
+    }
+
+    Ok(result)
+}
+
+// resets the registers and whichever memory words `insns` loads from, since programs always
+// start out with them zeroed
+fn prologue(insns: &[bpf_insn_t]) -> Vec<bpf_insn_t> {
+    let mut result = vec![stmt(BPF_LD | BPF_IMM, 0), stmt(BPF_LDX | BPF_IMM, 0)];
+    let mut words: Vec<u32> = insns
+        .iter()
+        .filter(|insn| insn.code == BPF_LD | BPF_MEM || insn.code == BPF_LDX | BPF_MEM)
+        .map(|insn| insn.k)
+        .collect();
+    words.sort_unstable();
+    words.dedup();
+    result.extend(words.into_iter().map(|k| stmt(BPF_ST, k)));
+    result
+}
+
+// follows `first`, rewritten according to `accept` and `reject`, with `second`
+fn splice(
+    first: &[bpf_insn_t],
+    accept: Action,
+    reject: Action,
+    second: &[bpf_insn_t],
+) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    let mut result = rewrite(first, accept, reject)?;
+    result.extend(prologue(second));
+    result.extend_from_slice(second);
+    Ok(result)
+}
+
+// runs `second` only if `first` accepts the packet
+pub(crate) fn and(
+    first: &[bpf_insn_t],
+    second: &[bpf_insn_t],
+) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    splice(first, Action::Continue, Action::Keep, second)
+}
+
+// runs `second` only if `first` rejects the packet
+pub(crate) fn or(
+    first: &[bpf_insn_t],
+    second: &[bpf_insn_t],
+) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    splice(first, Action::Keep, Action::Continue, second)
+}
+
+// swaps the program's accepting and rejecting `ret`s, where rejected packets are now accepted
+// with the longest accept length the program returns anywhere (or 65535 if there is none)
+pub(crate) fn negate(insns: &[bpf_insn_t]) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    let len = insns
+        .iter()
+        .filter_map(|insn| ret_value(insn).flatten())
+        .max()
+        .filter(|&len| len != 0)
+        .unwrap_or(65535);
+    rewrite(insns, Action::Return(0), Action::Return(len))
+}
@@ -0,0 +1,83 @@
+// src/context.rs
+
+use std::ptr;
+
+use crate::{bpf_copfunc_t, bpf_ctx_t};
+
+/// The largest external memory store bpfjit supports outside of the kernel
+pub const BPF_MEMWORDS: usize = 16;
+
+/// Extra state made available to hand-written programs loaded with
+/// `BpfJit::from_insns_with_context()`
+///
+/// By default, programs use an internal memory store of 16 words (`M[0]` to `M[15]`). Setting
+/// `extwords` replaces it with an external store of up to `BPF_MEMWORDS` words, which is zeroed
+/// before every match.
+///
+/// Programs can also call the registered coprocessor functions, by index, with the
+/// `BPF_MISC | BPF_COP` (index in `k`) and `BPF_MISC | BPF_COPX` (index in `X`) instructions.
+#[derive(Debug)]
+pub struct BpfContext {
+    pub(crate) raw: bpf_ctx_t,
+    copfuncs: Vec<bpf_copfunc_t>, // owns the table that raw.copfuncs points into
+}
+
+impl BpfContext {
+    pub fn new() -> Self {
+        BpfContext {
+            raw: bpf_ctx_t {
+                copfuncs: ptr::null(),
+                nfuncs: 0,
+                extwords: 0,
+                preinited: 0,
+            },
+            copfuncs: Vec::new(),
+        }
+    }
+
+    /// Sets the number of words in the external memory store
+    ///
+    /// Panics if `extwords` is greater than `BPF_MEMWORDS`.
+    pub fn extwords(mut self, extwords: usize) -> Self {
+        assert!(
+            extwords <= BPF_MEMWORDS,
+            "extwords is greater than BPF_MEMWORDS"
+        );
+        self.raw.extwords = extwords;
+        self
+    }
+
+    /// Sets the coprocessor functions the program can call, replacing any registered before
+    pub fn copfuncs(mut self, copfuncs: &[bpf_copfunc_t]) -> Self {
+        self.copfuncs = copfuncs.to_vec();
+        self.update_copfuncs();
+        self
+    }
+
+    // points the raw context at our table, which bpfjit requires to be NULL when there are none
+    fn update_copfuncs(&mut self) {
+        self.raw.copfuncs = if self.copfuncs.is_empty() {
+            ptr::null()
+        } else {
+            self.copfuncs.as_ptr()
+        };
+        self.raw.nfuncs = self.copfuncs.len();
+    }
+}
+
+impl Clone for BpfContext {
+    fn clone(&self) -> Self {
+        let mut result = BpfContext {
+            raw: self.raw,
+            copfuncs: self.copfuncs.clone(),
+        };
+        result.update_copfuncs();
+        result
+    }
+}
+
+impl Default for BpfContext {
+    fn default() -> Self {
+        BpfContext::new()
+    }
+}
@@ -0,0 +1,75 @@
+// src/disasm.rs
+
+use crate::bpf_insn_t;
+use crate::opcode::*;
+
+// formats instruction `n` the same way libpcap's bpf_image() does
+pub(crate) fn image(insn: &bpf_insn_t, n: usize) -> String {
+    let k = insn.k;
+
+    let (mnemonic, operand) = match insn.code {
+        c if c == BPF_RET | BPF_K => ("ret", format!("#{}", k as i32)),
+        c if c == BPF_RET | BPF_A => ("ret", String::new()),
+        c if c == BPF_LD | BPF_W | BPF_ABS => ("ld", format!("[{}]", k as i32)),
+        c if c == BPF_LD | BPF_H | BPF_ABS => ("ldh", format!("[{}]", k as i32)),
+        c if c == BPF_LD | BPF_B | BPF_ABS => ("ldb", format!("[{}]", k as i32)),
+        c if c == BPF_LD | BPF_W | BPF_LEN => ("ld", String::from("#pktlen")),
+        c if c == BPF_LD | BPF_W | BPF_IND => ("ld", format!("[x + {}]", k as i32)),
+        c if c == BPF_LD | BPF_H | BPF_IND => ("ldh", format!("[x + {}]", k as i32)),
+        c if c == BPF_LD | BPF_B | BPF_IND => ("ldb", format!("[x + {}]", k as i32)),
+        c if c == BPF_LD | BPF_IMM => ("ld", format!("#0x{:x}", k)),
+        c if c == BPF_LDX | BPF_IMM => ("ldx", format!("#0x{:x}", k)),
+        c if c == BPF_LDX | BPF_W | BPF_LEN => ("ldx", String::from("#pktlen")),
+        c if c == BPF_LDX | BPF_MSH | BPF_B => ("ldxb", format!("4*([{}]&0xf)", k as i32)),
+        c if c == BPF_LD | BPF_MEM => ("ld", format!("M[{}]", k as i32)),
+        c if c == BPF_LDX | BPF_MEM => ("ldx", format!("M[{}]", k as i32)),
+        c if c == BPF_ST => ("st", format!("M[{}]", k as i32)),
+        c if c == BPF_STX => ("stx", format!("M[{}]", k as i32)),
+        c if c == BPF_JMP | BPF_JA => ("ja", format!("{}", (n as u32 + 1).wrapping_add(k))),
+        c if c == BPF_JMP | BPF_JGT | BPF_K => ("jgt", format!("#0x{:x}", k)),
+        c if c == BPF_JMP | BPF_JGE | BPF_K => ("jge", format!("#0x{:x}", k)),
+        c if c == BPF_JMP | BPF_JEQ | BPF_K => ("jeq", format!("#0x{:x}", k)),
+        c if c == BPF_JMP | BPF_JSET | BPF_K => ("jset", format!("#0x{:x}", k)),
+        c if c == BPF_JMP | BPF_JGT | BPF_X => ("jgt", String::from("x")),
+        c if c == BPF_JMP | BPF_JGE | BPF_X => ("jge", String::from("x")),
+        c if c == BPF_JMP | BPF_JEQ | BPF_X => ("jeq", String::from("x")),
+        c if c == BPF_JMP | BPF_JSET | BPF_X => ("jset", String::from("x")),
+        c if c == BPF_ALU | BPF_ADD | BPF_X => ("add", String::from("x")),
+        c if c == BPF_ALU | BPF_SUB | BPF_X => ("sub", String::from("x")),
+        c if c == BPF_ALU | BPF_MUL | BPF_X => ("mul", String::from("x")),
+        c if c == BPF_ALU | BPF_DIV | BPF_X => ("div", String::from("x")),
+        c if c == BPF_ALU | BPF_MOD | BPF_X => ("mod", String::from("x")),
+        c if c == BPF_ALU | BPF_AND | BPF_X => ("and", String::from("x")),
+        c if c == BPF_ALU | BPF_OR | BPF_X => ("or", String::from("x")),
+        c if c == BPF_ALU | BPF_XOR | BPF_X => ("xor", String::from("x")),
+        c if c == BPF_ALU | BPF_LSH | BPF_X => ("lsh", String::from("x")),
+        c if c == BPF_ALU | BPF_RSH | BPF_X => ("rsh", String::from("x")),
+        c if c == BPF_ALU | BPF_ADD | BPF_K => ("add", format!("#{}", k as i32)),
+        c if c == BPF_ALU | BPF_SUB | BPF_K => ("sub", format!("#{}", k as i32)),
+        c if c == BPF_ALU | BPF_MUL | BPF_K => ("mul", format!("#{}", k as i32)),
+        c if c == BPF_ALU | BPF_DIV | BPF_K => ("div", format!("#{}", k as i32)),
+        c if c == BPF_ALU | BPF_MOD | BPF_K => ("mod", format!("#{}", k as i32)),
+        c if c == BPF_ALU | BPF_AND | BPF_K => ("and", format!("#0x{:x}", k)),
+        c if c == BPF_ALU | BPF_OR | BPF_K => ("or", format!("#0x{:x}", k)),
+        c if c == BPF_ALU | BPF_XOR | BPF_K => ("xor", format!("#0x{:x}", k)),
+        c if c == BPF_ALU | BPF_LSH | BPF_K => ("lsh", format!("#{}", k as i32)),
+        c if c == BPF_ALU | BPF_RSH | BPF_K => ("rsh", format!("#{}", k as i32)),
+        c if c == BPF_ALU | BPF_NEG => ("neg", String::new()),
+        c if c == BPF_MISC | BPF_TAX => ("tax", String::new()),
+        c if c == BPF_MISC | BPF_TXA => ("txa", String::new()),
+        c => ("unimp", format!("0x{:x}", c)),
+    };
+
+    if class(insn.code) == BPF_JMP && op(insn.code) != BPF_JA {
+        format!(
+            "({:03}) {:<8} {:<16} jt {}\tjf {}",
+            n,
+            mnemonic,
+            operand,
+            n + 1 + insn.jt as usize,
+            n + 1 + insn.jf as usize
+        )
+    } else {
+        format!("({:03}) {:<8} {}", n, mnemonic, operand)
+    }
+}
@@ -0,0 +1,133 @@
+// src/error.rs
+
+use std::error::Error;
+use std::ffi;
+use std::fmt;
+use std::io;
+use std::sync;
+
+use crate::LinkType;
+
+#[derive(Debug)]
+pub enum BpfJitError {
+    /// libpcap rejected the filter expression; carries the message from `pcap_geterr()` and,
+    /// if the message quotes part of the filter, the byte offset of that part
+    Compile {
+        message: String,
+        offset: Option<usize>,
+    },
+    /// sljit could not generate native code for the compiled program
+    Jit,
+    /// the filter expression contains an interior NUL byte, at `NulError::nul_position()`
+    NulByte(ffi::NulError),
+    /// the lock guarding the cache of `BpfJit::new_cached()` was poisoned by a panicking thread
+    LockPoisoned,
+    /// `bpf_validate()` rejected the program as unsafe to run
+    InvalidProgram,
+    /// the program has no instructions
+    EmptyProgram,
+    /// the compiled program has more instructions than `BpfJitBuilder::max_instructions()`
+    /// allows
+    ProgramTooLarge { len: usize, limit: usize },
+    /// a textual program dump could not be parsed; carries the 1-based line number and its text
+    Parse { line: usize, content: String },
+    /// a serialized program was truncated or has an unknown header
+    InvalidFormat,
+    /// libpcap doesn't know the link-layer type name, or it isn't one `LinkType` supports
+    UnknownLinkType(String),
+    /// `BpfJit::matches_bounded()` executed as many instructions as it was allowed to
+    StepLimitExceeded,
+    /// the filters being combined were compiled for different link-layer types
+    LinktypeMismatch(LinkType, LinkType),
+    /// every way the filter has of accepting a packet loads at least `offset` bytes of it, but
+    /// packets are captured with a smaller `snaplen`, so none can match
+    OffsetBeyondSnaplen { offset: u32, snaplen: i32 },
+    /// the file containing the filter expression could not be read
+    Io(io::Error),
+}
+
+impl fmt::Display for BpfJitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BpfJitError::Compile { message, .. } => {
+                write!(f, "could not compile cBPF expression: {}", message)
+            }
+            BpfJitError::Jit => write!(f, "could not JIT cBPF expression"),
+            BpfJitError::NulByte(err) => write!(
+                f,
+                "invalid cBPF expression: NUL byte at offset {}",
+                err.nul_position()
+            ),
+            BpfJitError::LockPoisoned => write!(f, "compiled program cache lock is poisoned"),
+            BpfJitError::InvalidProgram => write!(f, "invalid cBPF program"),
+            BpfJitError::EmptyProgram => write!(f, "empty cBPF program"),
+            BpfJitError::ProgramTooLarge { len, limit } => write!(
+                f,
+                "cBPF program has {} instructions, more than the limit of {}",
+                len, limit
+            ),
+            BpfJitError::InvalidFormat => write!(f, "invalid serialized cBPF program"),
+            BpfJitError::UnknownLinkType(name) => write!(f, "unknown link-layer type: {}", name),
+            BpfJitError::StepLimitExceeded => write!(f, "cBPF program exceeded its step limit"),
+            BpfJitError::LinktypeMismatch(first, second) => write!(
+                f,
+                "cannot combine filters for different link-layer types: {:?} and {:?}",
+                first, second
+            ),
+            BpfJitError::OffsetBeyondSnaplen { offset, snaplen } => write!(
+                f,
+                "cBPF program needs {} bytes of packets truncated to a snaplen of {}",
+                offset, snaplen
+            ),
+            BpfJitError::Io(err) => write!(f, "could not read cBPF expression: {}", err),
+            BpfJitError::Parse { line, content } => {
+                write!(
+                    f,
+                    "could not parse cBPF instruction on line {}: {:?}",
+                    line, content
+                )
+            }
+        }
+    }
+}
+
+impl BpfJitError {
+    // libpcap quotes the offending token in messages like "unknown port 'notanumber'"
+    #[cfg(feature = "pcap")]
+    pub(crate) fn compile(filter: &str, message: String) -> Self {
+        let offset = message
+            .split('\'')
+            .nth(1)
+            .filter(|token| !token.is_empty())
+            .and_then(|token| filter.find(token));
+        BpfJitError::Compile { message, offset }
+    }
+}
+
+impl Error for BpfJitError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BpfJitError::NulByte(err) => Some(err),
+            BpfJitError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ffi::NulError> for BpfJitError {
+    fn from(err: ffi::NulError) -> Self {
+        BpfJitError::NulByte(err)
+    }
+}
+
+impl From<io::Error> for BpfJitError {
+    fn from(err: io::Error) -> Self {
+        BpfJitError::Io(err)
+    }
+}
+
+impl<T> From<sync::PoisonError<T>> for BpfJitError {
+    fn from(_: sync::PoisonError<T>) -> Self {
+        BpfJitError::LockPoisoned
+    }
+}
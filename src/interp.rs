@@ -0,0 +1,134 @@
+// src/interp.rs
+
+use crate::opcode::*;
+use crate::{bpf_insn_t, BpfJitError, BPF_MEMWORDS};
+
+// loads `width` big-endian bytes at `offset`, or None if they are out of range
+fn load(pkt: &[u8], offset: usize, width: usize) -> Option<u32> {
+    let bytes = pkt.get(offset..offset.checked_add(width)?)?;
+    Some(bytes.iter().fold(0, |word, &byte| word << 8 | byte as u32))
+}
+
+/// The instructions a filter executed for a packet, as returned by `BpfJit::explain()`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MatchTrace {
+    /// the indices of the executed instructions, in order
+    pub steps: Vec<usize>,
+    /// the index of the `ret` that ended the program, or None if the packet was rejected by a
+    /// load past its end or a division by zero
+    pub ret: Option<usize>,
+    /// how many bytes of the packet the filter accepts, which is zero if it does not match
+    pub value: u32,
+}
+
+impl MatchTrace {
+    /// Returns whether the packet matched the filter
+    pub fn matched(&self) -> bool {
+        self.value != 0
+    }
+}
+
+// runs `insns` like libpcap's bpf_filter() does, but gives up after executing `max_steps`
+// instructions; packets that are too short for a load, or that divide by zero, are rejected.
+// the index of every executed instruction is pushed to `trace`, if given
+pub(crate) fn run(
+    insns: &[bpf_insn_t],
+    pkt: &[u8],
+    wirelen: u32,
+    max_steps: usize,
+    mut trace: Option<&mut Vec<usize>>,
+) -> Result<u32, BpfJitError> {
+    let mut a: u32 = 0;
+    let mut x: u32 = 0;
+    let mut mem = [0u32; BPF_MEMWORDS];
+    let mut pc = 0;
+
+    for _ in 0..max_steps {
+        let insn = insns.get(pc).ok_or(BpfJitError::InvalidProgram)?;
+        if let Some(trace) = trace.as_mut() {
+            trace.push(pc);
+        }
+        let k = insn.k;
+        let ind = (x as usize).checked_add(k as usize);
+        pc += 1;
+
+        // the width and offset of packet loads, which reject the packet if it is too short
+        let packet_load = match insn.code {
+            c if c == BPF_LD | BPF_W | BPF_ABS => Some((4, Some(k as usize))),
+            c if c == BPF_LD | BPF_H | BPF_ABS => Some((2, Some(k as usize))),
+            c if c == BPF_LD | BPF_B | BPF_ABS => Some((1, Some(k as usize))),
+            c if c == BPF_LD | BPF_W | BPF_IND => Some((4, ind)),
+            c if c == BPF_LD | BPF_H | BPF_IND => Some((2, ind)),
+            c if c == BPF_LD | BPF_B | BPF_IND => Some((1, ind)),
+            _ => None,
+        };
+        if let Some((width, offset)) = packet_load {
+            match offset.and_then(|offset| load(pkt, offset, width)) {
+                Some(value) => a = value,
+                None => return Ok(0),
+            }
+            continue;
+        }
+
+        let word = |k: u32| {
+            mem.get(k as usize)
+                .copied()
+                .ok_or(BpfJitError::InvalidProgram)
+        };
+        match insn.code {
+            c if c == BPF_LD | BPF_IMM => a = k,
+            c if c == BPF_LD | BPF_LEN => a = wirelen,
+            c if c == BPF_LD | BPF_MEM => a = word(k)?,
+            c if c == BPF_LDX | BPF_IMM => x = k,
+            c if c == BPF_LDX | BPF_LEN => x = wirelen,
+            c if c == BPF_LDX | BPF_MEM => x = word(k)?,
+            c if c == BPF_LDX | BPF_B | BPF_MSH => match load(pkt, k as usize, 1) {
+                Some(value) => x = (value & 0xf) << 2,
+                None => return Ok(0),
+            },
+            c if c == BPF_ST || c == BPF_STX => {
+                let value = if c == BPF_ST { a } else { x };
+                *mem.get_mut(k as usize).ok_or(BpfJitError::InvalidProgram)? = value;
+            }
+            c if c == BPF_RET | BPF_K => return Ok(k),
+            c if c == BPF_RET | BPF_A => return Ok(a),
+            c if c == BPF_MISC | BPF_TAX => x = a,
+            c if c == BPF_MISC | BPF_TXA => a = x,
+            c if c == BPF_JMP | BPF_JA => pc += k as usize,
+            c if class(c) == BPF_JMP => {
+                let operand = if c & BPF_X != 0 { x } else { k };
+                let taken = match op(c) {
+                    BPF_JEQ => a == operand,
+                    BPF_JGT => a > operand,
+                    BPF_JGE => a >= operand,
+                    BPF_JSET => a & operand != 0,
+                    _ => return Err(BpfJitError::InvalidProgram),
+                };
+                pc += if taken { insn.jt } else { insn.jf } as usize;
+            }
+            c if class(c) == BPF_ALU => {
+                let operand = if c & BPF_X != 0 { x } else { k };
+                a = match op(c) {
+                    BPF_ADD => a.wrapping_add(operand),
+                    BPF_SUB => a.wrapping_sub(operand),
+                    BPF_MUL => a.wrapping_mul(operand),
+                    BPF_DIV if operand == 0 => return Ok(0),
+                    BPF_DIV => a / operand,
+                    BPF_MOD if operand == 0 => return Ok(0),
+                    BPF_MOD => a % operand,
+                    BPF_OR => a | operand,
+                    BPF_AND => a & operand,
+                    BPF_XOR => a ^ operand,
+                    BPF_LSH => a.checked_shl(operand).unwrap_or(0),
+                    BPF_RSH => a.checked_shr(operand).unwrap_or(0),
+                    BPF_NEG => a.wrapping_neg(),
+                    _ => return Err(BpfJitError::InvalidProgram),
+                };
+            }
+            // including coprocessor calls, which only JIT'ed programs can make
+            _ => return Err(BpfJitError::InvalidProgram),
+        }
+    }
+
+    Err(BpfJitError::StepLimitExceeded)
+}
@@ -1,32 +1,123 @@
 // src/lib.rs
 
-use std::error::Error;
+#[cfg(feature = "pcap")]
+mod builder;
+mod capi;
+mod combine;
+mod context;
+mod disasm;
+mod error;
+mod interp;
+mod linktype;
+mod matcher;
+#[cfg(feature = "pcap")]
+mod names;
+mod opcode;
+mod parse;
+mod serialize;
+mod socket;
+mod stats;
+
+#[cfg(feature = "pcap")]
+pub use builder::{BpfJitBuilder, CompileOptions};
+#[cfg(feature = "pcap")]
+pub use capi::bpfjit_sys_compile;
+pub use capi::{bpfjit_error_t, bpfjit_sys_free, bpfjit_sys_matches};
+pub use context::{BpfContext, BPF_MEMWORDS};
+pub use error::BpfJitError;
+pub use interp::MatchTrace;
+pub use linktype::LinkType;
+pub use matcher::Matcher;
+pub use socket::SockFilter;
+#[cfg(target_os = "linux")]
+pub use socket::SockFprog;
+pub use stats::ProgramStats;
+
+#[cfg(feature = "pcap")]
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::ffi;
+use std::fmt;
+#[cfg(feature = "pcap")]
+use std::fs;
+use std::hash;
 use std::mem;
+#[cfg(feature = "pcap")]
+use std::mem::MaybeUninit;
+#[cfg(feature = "pcap")]
+use std::path::Path;
+use std::ptr;
+use std::slice;
+#[cfg(feature = "pcap")]
 use std::sync;
+#[cfg(feature = "pcap")]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(feature = "pcap")]
 use lazy_static::lazy_static;
-use libc;
 
+/// A single classic BPF instruction, laid out like libpcap's `struct bpf_insn`
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
-struct bpf_insn_t {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct bpf_insn_t {
     pub code: libc::c_ushort,
     pub jt: libc::c_uchar,
     pub jf: libc::c_uchar,
     pub k: libc::c_uint,
 }
 
+/// A cBPF program, laid out like libpcap's `struct bpf_program`
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
-struct bpf_program_t {
+pub struct bpf_program_t {
     pub bf_len: libc::c_uint,
     pub bf_insns: *mut bpf_insn_t,
 }
 
+impl bpf_program_t {
+    // copies `insns` into a malloc()'d buffer, so the result can be released with pcap_freecode()
+    unsafe fn from_insns(insns: &[bpf_insn_t]) -> bpf_program_t {
+        let size = mem::size_of_val(insns);
+        let buf = libc::malloc(size) as *mut bpf_insn_t;
+        if size != 0 {
+            if buf.is_null() {
+                panic!("could not allocate cBPF program");
+            }
+            ptr::copy_nonoverlapping(insns.as_ptr(), buf, insns.len());
+        }
+
+        bpf_program_t {
+            bf_len: insns.len() as libc::c_uint,
+            bf_insns: buf,
+        }
+    }
+
+    // releases the instructions, whether libpcap compiled them or we copied them
+    unsafe fn free(&mut self) {
+        #[cfg(feature = "pcap")]
+        pcap_freecode(self); // also resets bf_insns to NULL
+
+        #[cfg(not(feature = "pcap"))]
+        {
+            libc::free(self.bf_insns as *mut libc::c_void);
+            self.bf_insns = ptr::null_mut();
+            self.bf_len = 0;
+        }
+    }
+
+    unsafe fn insns(&self) -> &[bpf_insn_t] {
+        if self.bf_insns.is_null() {
+            &[]
+        } else {
+            slice::from_raw_parts(self.bf_insns, self.bf_len as usize)
+        }
+    }
+}
+
+/// The arguments a program runs with, laid out like bpfjit's `bpf_args_t`
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
-struct bpf_args_t {
+pub struct bpf_args_t {
     pub pkt: *const libc::c_uchar,
     pub wirelen: libc::size_t,
     pub buflen: libc::size_t,
@@ -34,19 +125,27 @@ struct bpf_args_t {
     pub arg: *mut ffi::c_void,
 }
 
+/// The context a program is JIT'ed and run with, laid out like bpfjit's `bpf_ctx_t`
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
-struct bpf_ctx_t {
-    pub copfuncs: *const ffi::c_void,
+pub struct bpf_ctx_t {
+    pub copfuncs: *const bpf_copfunc_t,
     pub nfuncs: libc::size_t,
     pub extwords: libc::size_t,
     pub preinited: libc::c_uint,
 }
 
+/// A coprocessor function, called by the `BPF_MISC | BPF_COP` and `BPF_MISC | BPF_COPX`
+/// instructions with the value of the accumulator, which is replaced with the return value
+#[allow(non_camel_case_types)]
+pub type bpf_copfunc_t = extern "C" fn(ctx: *const bpf_ctx_t, args: *mut bpf_args_t, a: u32) -> u32;
+
+#[allow(non_camel_case_types)]
 type bpfjit_func_t =
     Option<unsafe extern "C" fn(ctx: *const bpf_ctx_t, args: *mut bpf_args_t) -> libc::c_uint>;
 
-#[link(name = "pcap")]
+// linked by build.rs, either dynamically or statically
+#[cfg(feature = "pcap")]
 extern "C" {
     #[link_name = "pcap_open_dead"]
     fn pcap_open_dead(linktype: libc::c_int, snaplen: libc::c_int) -> *mut ffi::c_void;
@@ -60,11 +159,47 @@ extern "C" {
         netmask: libc::c_uint,
     ) -> libc::c_int;
 
+    #[link_name = "pcap_compile_nopcap"]
+    fn pcap_compile_nopcap(
+        snaplen: libc::c_int,
+        linktype: libc::c_int,
+        fp: *mut bpf_program_t,
+        str: *const libc::c_char,
+        optimize: libc::c_int,
+        netmask: libc::c_uint,
+    ) -> libc::c_int;
+
     #[link_name = "pcap_close"]
     fn pcap_close(p: *mut ffi::c_void);
 
     #[link_name = "pcap_geterr"]
     fn pcap_geterr(p: *mut ffi::c_void) -> *const libc::c_char;
+
+    #[link_name = "pcap_lib_version"]
+    fn pcap_lib_version() -> *const libc::c_char;
+
+    #[link_name = "pcap_datalink_name_to_val"]
+    fn pcap_datalink_name_to_val(name: *const libc::c_char) -> libc::c_int;
+
+    #[link_name = "pcap_datalink_val_to_name"]
+    fn pcap_datalink_val_to_name(dlt: libc::c_int) -> *const libc::c_char;
+
+    #[link_name = "pcap_freecode"]
+    fn pcap_freecode(fp: *mut bpf_program_t);
+
+    #[link_name = "bpf_validate"]
+    fn bpf_validate(f: *const bpf_insn_t, len: libc::c_int) -> libc::c_int;
+
+    #[link_name = "bpf_image"]
+    fn bpf_image(p: *const bpf_insn_t, n: libc::c_int) -> *const libc::c_char;
+
+    #[link_name = "bpf_filter"]
+    fn bpf_filter(
+        pc: *const bpf_insn_t,
+        pkt: *const libc::c_uchar,
+        wirelen: libc::c_uint,
+        buflen: libc::c_uint,
+    ) -> libc::c_uint;
 }
 
 extern "C" {
@@ -79,127 +214,1226 @@ extern "C" {
     fn bpfjit_free_code(func: bpfjit_func_t);
 }
 
+/// Netmask to pass when the capture interface's netmask is not known
+pub const PCAP_NETMASK_UNKNOWN: u32 = 0xffffffff;
+
+#[cfg(feature = "pcap")]
 lazy_static! {
     static ref BIGLOCK: sync::Mutex<u8> = sync::Mutex::new(0);
+    static ref PCAP_VERSION: String = unsafe {
+        ffi::CStr::from_ptr(pcap_lib_version())
+            .to_string_lossy()
+            .into_owned()
+    };
+    static ref CACHE: sync::Mutex<HashMap<(String, BpfJitBuilder), sync::Arc<BpfJit>>> =
+        sync::Mutex::new(HashMap::new());
+    static ref PCAP_IS_THREAD_SAFE: bool =
+        parse_pcap_version(&PCAP_VERSION).is_some_and(|version| version >= (1, 8));
+}
+
+#[cfg(feature = "pcap")]
+static CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
+
+// serializes calls into libpcap that aren't thread-safe; the lock guards no data of its own, so
+// a thread that panicked while holding it can't have left anything inconsistent behind
+#[cfg(feature = "pcap")]
+fn biglock() -> sync::MutexGuard<'static, u8> {
+    BIGLOCK.lock().unwrap_or_else(sync::PoisonError::into_inner)
+}
+
+/// Returns the version banner of the linked libpcap, e.g. `"libpcap version 1.10.3"`
+#[cfg(feature = "pcap")]
+pub fn libpcap_version() -> &'static str {
+    &PCAP_VERSION
+}
+
+/// Returns the libpcap `DLT_*` value for a link-layer type name, e.g. `"EN10MB"` or
+/// `"LINUX_SLL"`, or `None` if libpcap doesn't know the name
+#[cfg(feature = "pcap")]
+pub fn linktype_from_name(name: &str) -> Option<i32> {
+    let name = ffi::CString::new(name).ok()?;
+    match unsafe { pcap_datalink_name_to_val(name.as_ptr()) } {
+        -1 => None,
+        dlt => Some(dlt),
+    }
+}
+
+/// Returns the `DLT_*` value and name of every link-layer type the linked libpcap knows about
+#[cfg(feature = "pcap")]
+pub fn supported_linktypes() -> Vec<(i32, String)> {
+    // the DLT_* values libpcap assigns currently stop well short of this
+    (0..1024)
+        .filter_map(|dlt| unsafe {
+            let name = pcap_datalink_val_to_name(dlt);
+            if name.is_null() {
+                None
+            } else {
+                Some((
+                    dlt,
+                    ffi::CStr::from_ptr(name).to_string_lossy().into_owned(),
+                ))
+            }
+        })
+        .collect()
+}
+
+// extracts the major and minor version from e.g. "libpcap version 1.10.3 (with TPACKET_V3)"
+#[cfg(feature = "pcap")]
+fn parse_pcap_version(version: &str) -> Option<(u32, u32)> {
+    let version = version.split("version ").nth(1)?;
+    let mut numbers = version.split(|c: char| !c.is_ascii_digit());
+    let major = numbers.next()?.parse().ok()?;
+    let minor = numbers.next()?.parse().ok()?;
+    Some((major, minor))
 }
 
 pub struct BpfJit {
     prog: bpf_program_t,
-    ctx: *const bpf_ctx_t,
+    ctx: Option<BpfContext>,
     cb: bpfjit_func_t,
+    source: String,
+    linktype: Option<LinkType>,
+    // the options the filter was compiled with, if it was compiled from an expression
+    #[cfg(feature = "pcap")]
+    options: Option<BpfJitBuilder>,
 }
 
 impl BpfJit {
-    pub fn new(filter: &str) -> Result<Self, Box<Error>> {
+    #[cfg(feature = "pcap")]
+    pub fn new(filter: &str) -> Result<Self, BpfJitError> {
         BpfJit::new_ethernet(filter)
     }
 
-    pub fn new_ethernet(filter: &str) -> Result<Self, Box<Error>> {
+    /// Compiles `filter` for packets of the given link-layer type
+    #[cfg(feature = "pcap")]
+    pub fn new_with_linktype(filter: &str, linktype: LinkType) -> Result<Self, BpfJitError> {
+        BpfJit::new_with_snaplen(filter, linktype, 65535)
+    }
+
+    /// Compiles `filter` for packets of the link-layer type with the given libpcap name, e.g.
+    /// `"EN10MB"` or `"LINUX_SLL"`
+    #[cfg(feature = "pcap")]
+    pub fn new_with_linktype_name(filter: &str, name: &str) -> Result<Self, BpfJitError> {
+        match linktype_from_name(name) {
+            Some(dlt) => {
+                let linktype = LinkType::from_dlt(dlt).unwrap_or(LinkType::Other(dlt));
+                BpfJit::new_with_linktype(filter, linktype)
+            }
+            None => Err(BpfJitError::UnknownLinkType(name.to_string())),
+        }
+    }
+
+    /// Compiles `filter` as if for a capture with the given snapshot length, which becomes the
+    /// accept length returned for matching packets
+    #[cfg(feature = "pcap")]
+    pub fn new_with_snaplen(
+        filter: &str,
+        linktype: LinkType,
+        snaplen: i32,
+    ) -> Result<Self, BpfJitError> {
+        BpfJitBuilder::new()
+            .linktype(linktype)
+            .snaplen(snaplen)
+            .compile(filter)
+    }
+
+    /// Compiles `filter` using the IPv4 netmask (in host byte order) of the capture interface,
+    /// which expressions like `ip broadcast` need in order to compile
+    #[cfg(feature = "pcap")]
+    pub fn new_with_netmask(
+        filter: &str,
+        linktype: LinkType,
+        netmask: u32,
+    ) -> Result<Self, BpfJitError> {
+        BpfJitBuilder::new()
+            .linktype(linktype)
+            .netmask(netmask)
+            .compile(filter)
+    }
+
+    /// Compiles `filter` without running libpcap's optimizer over the generated program, which
+    /// is mostly useful for debugging or for reproducing another tool's bytecode
+    #[cfg(feature = "pcap")]
+    pub fn new_unoptimized(filter: &str, linktype: LinkType) -> Result<Self, BpfJitError> {
+        BpfJitBuilder::new()
+            .linktype(linktype)
+            .optimize(false)
+            .compile(filter)
+    }
+
+    /// Compiles `filter` with the given options
+    #[cfg(feature = "pcap")]
+    pub fn with_options(filter: &str, options: CompileOptions) -> Result<Self, BpfJitError> {
+        BpfJitBuilder::from(options).compile(filter)
+    }
+
+    /// Compiles `filter` with the given options, or clones the result of an earlier call with
+    /// the same filter and options
+    ///
+    /// Each clone JITs its own copy of the cached program, but doesn't go through libpcap.
+    #[cfg(feature = "pcap")]
+    pub fn new_cached(filter: &str, options: &BpfJitBuilder) -> Result<Self, BpfJitError> {
+        let key = (filter.to_string(), *options);
+        let cached = CACHE.lock()?.get(&key).cloned();
+        let cached = match cached {
+            Some(cached) => {
+                CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+                cached
+            }
+            None => {
+                let compiled = sync::Arc::new(options.compile(filter)?);
+                CACHE.lock()?.entry(key).or_insert(compiled).clone()
+            }
+        };
+
+        Ok((*cached).clone())
+    }
+
+    /// Replaces the program with `filter`, compiled with the same options as this filter
+    ///
+    /// Programs that weren't compiled from an expression have no options to reuse, so they are
+    /// recompiled with the default options (including the default snaplen and netmask) for the
+    /// same link-layer type, or Ethernet. The context set with `from_insns_with_context()`, if
+    /// any, is kept, and the new program is JIT'ed against it.
+    ///
+    /// The old program is only freed once the new one has been compiled and JIT'ed, so on
+    /// error the filter is left unchanged.
+    #[cfg(feature = "pcap")]
+    pub fn recompile(&mut self, filter: &str) -> Result<(), BpfJitError> {
+        let linktype = self.linktype.unwrap_or(LinkType::Ethernet);
+        let options = self
+            .options
+            .unwrap_or_else(|| BpfJitBuilder::new().linktype(linktype));
+
+        unsafe {
+            let prog = BpfJit::compile_program(filter, &options)?;
+            let cb = if options.jit {
+                bpfjit_generate_code(self.ctx_ptr(), prog.bf_insns, prog.bf_len as libc::size_t)
+            } else {
+                None
+            };
+
+            let mut old_prog = mem::replace(&mut self.prog, prog);
+            let old_cb = mem::replace(&mut self.cb, cb);
+            self.source = filter.to_string();
+            self.linktype = Some(options.linktype);
+            self.options = Some(options);
+
+            if old_cb.is_some() {
+                bpfjit_free_code(old_cb);
+            }
+            old_prog.free();
+        }
+        Ok(())
+    }
+
+    /// Returns how many calls to `new_cached()` found their program in the cache
+    #[cfg(feature = "pcap")]
+    pub fn cache_hits() -> usize {
+        CACHE_HITS.load(Ordering::Relaxed)
+    }
+
+    /// Compiles `filter` with the given options, returning a copy of the compiled bytecode (e.g.
+    /// to attach it to a socket as well) along with the filter itself
+    #[cfg(feature = "pcap")]
+    pub fn compile_with_bytecode(
+        filter: &str,
+        options: &BpfJitBuilder,
+    ) -> Result<(Self, Vec<bpf_insn_t>), BpfJitError> {
+        let compiled = options.compile(filter)?;
+        let insns = compiled.instructions().to_vec();
+        Ok((compiled, insns))
+    }
+
+    /// Compiles `filter` with the given options, returning just the bytecode, without JITing
+    /// it (e.g. to inspect or serialize it)
+    #[cfg(feature = "pcap")]
+    pub fn compile_only(
+        filter: &str,
+        options: &BpfJitBuilder,
+    ) -> Result<Vec<bpf_insn_t>, BpfJitError> {
         unsafe {
-            let mut result: BpfJit = mem::zeroed();
+            let mut prog = BpfJit::compile_program(filter, options)?;
+            let insns = prog.insns().to_vec();
+            prog.free();
+            Ok(insns)
+        }
+    }
 
-            let lock = BIGLOCK.lock()?; // pcap_compile() in libpcap < 1.8 is not thread-safe
+    #[cfg(feature = "pcap")]
+    fn compile(filter: &str, options: &BpfJitBuilder) -> Result<Self, BpfJitError> {
+        unsafe {
+            let prog = BpfJit::compile_program(filter, options)?;
 
-            let pcap = pcap_open_dead(1 /* LINKTYPE_ETHERNET */, 65535);
-            let compiled = pcap_compile(
-                pcap,
-                &mut result.prog,
-                ffi::CString::new(filter)?.as_ptr(),
-                1,
-                0xffffffff,
+            // programs generated by libpcap are always valid, so if sljit can't JIT them we
+            // can safely fall back to libpcap's bpf_filter() interpreter instead
+            let mut result = BpfJit::from_program(
+                prog,
+                None,
+                filter.to_string(),
+                Some(options.linktype),
+                options.jit,
             );
-            pcap_close(pcap);
+            result.options = Some(*options);
+            Ok(result)
+        }
+    }
+
+    // compiles `filter` with libpcap, into a program that must be freed with pcap_freecode()
+    #[cfg(feature = "pcap")]
+    unsafe fn compile_program(
+        filter: &str,
+        options: &BpfJitBuilder,
+    ) -> Result<bpf_program_t, BpfJitError> {
+        if !options.resolve_names {
+            if let Some(name) = names::find_name(filter) {
+                let message = format!("name resolution is disabled, cannot look up '{}'", name);
+                return Err(BpfJitError::compile(filter, message));
+            }
+        }
+
+        let mut prog = MaybeUninit::<bpf_program_t>::uninit();
 
-            drop(lock);
+        let cstr = ffi::CString::new(filter)?;
+
+        // pcap_compile() in libpcap < 1.8 is not thread-safe
+        let lock = if *PCAP_IS_THREAD_SAFE {
+            None
+        } else {
+            Some(biglock())
+        };
+
+        let compiled = pcap_compile_nopcap(
+            options.snaplen,
+            options.linktype.as_dlt(),
+            prog.as_mut_ptr(),
+            cstr.as_ptr(),
+            options.optimize as libc::c_int,
+            options.netmask,
+        );
+        let error = if compiled != 0 {
+            // pcap_compile_nopcap() doesn't say why it failed, so compile again with a handle
+            // of our own to find out
+            Some(BpfJit::compile_error(&cstr, options))
+        } else {
+            None
+        };
+
+        drop(lock);
+
+        if let Some(error) = error {
+            return Err(BpfJitError::compile(filter, error));
+        }
 
-            if compiled != 0 {
-                return Err(Box::from(format!(
-                    "could not compile cBPF expression: {}",
-                    ffi::CStr::from_ptr(pcap_geterr(pcap)).to_str().unwrap()
-                )));
+        let mut prog = prog.assume_init();
+        if prog.bf_len == 0 {
+            pcap_freecode(&mut prog);
+            return Err(BpfJitError::EmptyProgram);
+        }
+        if let Some(limit) = options.max_instructions {
+            let len = prog.bf_len as usize;
+            if len > limit {
+                pcap_freecode(&mut prog);
+                return Err(BpfJitError::ProgramTooLarge { len, limit });
             }
+        }
+
+        Ok(prog)
+    }
+
+    // compiles `filter` with a dead pcap handle, just for the error message
+    #[cfg(feature = "pcap")]
+    unsafe fn compile_error(filter: &ffi::CStr, options: &BpfJitBuilder) -> String {
+        let mut prog = MaybeUninit::<bpf_program_t>::uninit();
 
+        let pcap = pcap_open_dead(options.linktype.as_dlt(), options.snaplen);
+        let compiled = pcap_compile(
+            pcap,
+            prog.as_mut_ptr(),
+            filter.as_ptr(),
+            options.optimize as libc::c_int,
+            options.netmask,
+        );
+        let error = if compiled != 0 {
+            // the error buffer belongs to the handle, so copy it out before closing
+            ffi::CStr::from_ptr(pcap_geterr(pcap))
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            pcap_freecode(prog.as_mut_ptr());
+            String::from("unknown error")
+        };
+        pcap_close(pcap);
+
+        error
+    }
+
+    // takes ownership of `prog`, which must be valid, and JITs it if `jit` is set (falling back
+    // to the interpreter if that fails)
+    unsafe fn from_program(
+        prog: bpf_program_t,
+        ctx: Option<BpfContext>,
+        source: String,
+        linktype: Option<LinkType>,
+        jit: bool,
+    ) -> Self {
+        let mut result = BpfJit {
+            prog,
+            ctx,
+            cb: None,
+            source,
+            linktype,
+            #[cfg(feature = "pcap")]
+            options: None,
+        };
+
+        if jit {
             result.cb = bpfjit_generate_code(
-                result.ctx,
+                result.ctx_ptr(),
                 result.prog.bf_insns,
                 result.prog.bf_len as libc::size_t,
             );
-            if result.cb.is_none() {
-                return Err(Box::from("could not JIT cBPF expression"));
+        }
+
+        result
+    }
+
+    #[cfg(feature = "pcap")]
+    pub fn new_ethernet(filter: &str) -> Result<Self, BpfJitError> {
+        BpfJit::new_with_linktype(filter, LinkType::Ethernet)
+    }
+
+    /// Compiles `filter` for 802.1Q VLAN-tagged Ethernet frames, as `vlan and (filter)`
+    ///
+    /// Ethernet filters only see untagged frames unless they start with `vlan`, which shifts
+    /// the offsets of everything after it by the size of the tag; use `vlan 100 and ...` with
+    /// the other constructors to match a specific VLAN ID.
+    #[cfg(feature = "pcap")]
+    pub fn new_ethernet_vlan(filter: &str) -> Result<Self, BpfJitError> {
+        if filter.trim().is_empty() {
+            BpfJit::new_ethernet("vlan")
+        } else {
+            BpfJit::new_ethernet(&format!("vlan and ({})", filter))
+        }
+    }
+
+    #[cfg(feature = "pcap")]
+    pub fn new_ip(filter: &str) -> Result<Self, BpfJitError> {
+        BpfJit::new_with_linktype(filter, LinkType::RawIp)
+    }
+
+    /// Compiles `filter` for 802.11 frames with a radiotap header, as captured by wireless
+    /// interfaces in monitor mode, so that `wlan` filters (e.g. `wlan type mgt`) can be used
+    #[cfg(feature = "pcap")]
+    pub fn new_radiotap(filter: &str) -> Result<Self, BpfJitError> {
+        BpfJit::new_with_linktype(filter, LinkType::Radiotap)
+    }
+
+    /// Compiles `filter` for BSD loopback captures (`DLT_NULL`), as made on `lo`
+    ///
+    /// The 4-byte link-layer header holds the address family in the byte order of the machine
+    /// that captured the packet, not network byte order. libpcap checks for both orders when
+    /// filtering on `ip` or `ip6`, but filters that load the header directly (e.g. `link[0]`)
+    /// only work for captures made on a machine of the same endianness.
+    #[cfg(feature = "pcap")]
+    pub fn new_loopback(filter: &str) -> Result<Self, BpfJitError> {
+        BpfJit::new_with_linktype(filter, LinkType::Null)
+    }
+
+    /// Compiles `filter` for Linux "cooked" captures, as made on the `any` pseudo-device
+    #[cfg(feature = "pcap")]
+    pub fn new_linux_sll(filter: &str) -> Result<Self, BpfJitError> {
+        BpfJit::new_with_linktype(filter, LinkType::LinuxSll)
+    }
+
+    /// Compiles `filter` for version 2 Linux "cooked" captures, which libpcap 1.10 and later
+    /// make on the `any` pseudo-device
+    ///
+    /// Returns `BpfJitError::UnknownLinkType` if the linked libpcap predates `DLT_LINUX_SLL2`.
+    #[cfg(feature = "pcap")]
+    pub fn new_linux_sll2(filter: &str) -> Result<Self, BpfJitError> {
+        let supported =
+            unsafe { !pcap_datalink_val_to_name(LinkType::LinuxSll2.as_dlt()).is_null() };
+        if !supported {
+            return Err(BpfJitError::UnknownLinkType("LINUX_SLL2".to_string()));
+        }
+        BpfJit::new_with_linktype(filter, LinkType::LinuxSll2)
+    }
+
+    /// Compiles `filter` for raw IPv6 packets with no link-layer header, using `DLT_IPV6` if the
+    /// linked libpcap supports it, and `DLT_RAW` (which also accepts IPv4) otherwise
+    #[cfg(feature = "pcap")]
+    pub fn new_ipv6(filter: &str) -> Result<Self, BpfJitError> {
+        let supported = unsafe { !pcap_datalink_val_to_name(LinkType::Ipv6.as_dlt()).is_null() };
+        if supported {
+            BpfJit::new_with_linktype(filter, LinkType::Ipv6)
+        } else {
+            BpfJit::new_with_linktype(filter, LinkType::RawIp)
+        }
+    }
+
+    /// Compiles the Ethernet filter expression in the file at `path`, where lines starting
+    /// with `#` are comments and the remaining lines are joined with spaces
+    #[cfg(feature = "pcap")]
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, BpfJitError> {
+        let text = fs::read_to_string(path)?;
+        let filter = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join(" ");
+        BpfJit::new_ethernet(&filter)
+    }
+
+    /// JITs an already-compiled cBPF program (e.g. the output of `tcpdump -dd`), bypassing
+    /// libpcap's filter compiler
+    ///
+    /// The program is first checked with libpcap's `bpf_validate()`, so that untrusted bytecode
+    /// (e.g. with out-of-range jumps or no terminating `ret`) is rejected instead of executed.
+    /// Without the `pcap` feature, it is only checked by bpfjit itself, and rejected unless it
+    /// can be JIT'ed, since there is no interpreter to fall back to.
+    pub fn from_insns(insns: &[bpf_insn_t]) -> Result<Self, BpfJitError> {
+        if insns.is_empty() {
+            return Err(BpfJitError::EmptyProgram);
+        }
+
+        unsafe {
+            #[cfg(feature = "pcap")]
+            {
+                if bpf_validate(insns.as_ptr(), insns.len() as libc::c_int) == 0 {
+                    return Err(BpfJitError::InvalidProgram);
+                }
+            }
+
+            let result = BpfJit::from_program(
+                bpf_program_t::from_insns(insns),
+                None,
+                String::new(),
+                None,
+                true,
+            );
+
+            #[cfg(not(feature = "pcap"))]
+            {
+                if !result.is_jit() {
+                    return Err(BpfJitError::InvalidProgram);
+                }
             }
 
             Ok(result)
         }
     }
 
-    pub fn new_ip(filter: &str) -> Result<Self, Box<Error>> {
-        unsafe {
-            let mut result: BpfJit = mem::zeroed();
+    /// Takes ownership of a cBPF program built elsewhere, e.g. by another library's call to
+    /// `pcap_compile()`, and JITs it like `from_insns()` does
+    ///
+    /// The program is freed when the filter is dropped (with `pcap_freecode()`, or `free()`
+    /// without the `pcap` feature), and also if this returns an error, so it must not be used
+    /// or freed by the caller afterwards either way.
+    ///
+    /// # Safety
+    ///
+    /// `prog.bf_insns` must point to `prog.bf_len` instructions in a buffer allocated with
+    /// `malloc()`, or be NULL if `prog.bf_len` is zero, and nothing else may own that buffer.
+    pub unsafe fn from_raw_program(mut prog: bpf_program_t) -> Result<Self, BpfJitError> {
+        let insns = prog.insns();
+        if insns.is_empty() {
+            prog.free();
+            return Err(BpfJitError::EmptyProgram);
+        }
+
+        #[cfg(feature = "pcap")]
+        {
+            if bpf_validate(insns.as_ptr(), insns.len() as libc::c_int) == 0 {
+                prog.free();
+                return Err(BpfJitError::InvalidProgram);
+            }
+        }
+
+        // the program is freed along with the filter, even if it could not be JIT'ed
+        let result = BpfJit::from_program(prog, None, String::new(), None, true);
+
+        #[cfg(not(feature = "pcap"))]
+        {
+            if !result.is_jit() {
+                return Err(BpfJitError::InvalidProgram);
+            }
+        }
 
-            let lock = BIGLOCK.lock()?; // pcap_compile() in libpcap < 1.8 is not thread-safe
+        Ok(result)
+    }
+
+    /// JITs a hand-written cBPF program that runs with the given external memory store and
+    /// coprocessor functions
+    ///
+    /// These programs can't be checked with `bpf_validate()` or interpreted by libpcap, so they
+    /// are rejected with `BpfJitError::Jit` unless bpfjit accepts them.
+    pub fn from_insns_with_context(
+        insns: &[bpf_insn_t],
+        ctx: BpfContext,
+    ) -> Result<Self, BpfJitError> {
+        if insns.is_empty() {
+            return Err(BpfJitError::EmptyProgram);
+        }
 
-            let pcap = pcap_open_dead(12 /* LINKTYPE_RAW */, 65535);
-            let compiled = pcap_compile(
-                pcap,
-                &mut result.prog,
-                ffi::CString::new(filter)?.as_ptr(),
-                1,
-                0xffffffff,
+        unsafe {
+            let result = BpfJit::from_program(
+                bpf_program_t::from_insns(insns),
+                Some(ctx),
+                String::new(),
+                None,
+                true,
             );
-            pcap_close(pcap);
+            if !result.is_jit() {
+                return Err(BpfJitError::Jit);
+            }
+
+            Ok(result)
+        }
+    }
+
+    /// JITs a program given as the C array fragment printed by `tcpdump -dd`
+    pub fn from_cc(text: &str) -> Result<Self, BpfJitError> {
+        BpfJit::from_insns(&parse::parse_cc(text)?)
+    }
+
+    /// JITs a program given as the instruction count and decimal `code jt jf k` lines printed
+    /// by `tcpdump -ddd`
+    pub fn from_ddd(text: &str) -> Result<Self, BpfJitError> {
+        BpfJit::from_insns(&parse::parse_ddd(text)?)
+    }
+
+    /// JITs a program serialized with `to_bytes()`, which doesn't need libpcap's filter compiler
+    pub fn from_bytes(data: &[u8]) -> Result<Self, BpfJitError> {
+        BpfJit::from_insns(&serialize::from_bytes(data)?)
+    }
+
+    /// Builds a filter for packets of the given link-layer type that accepts every packet,
+    /// without going through libpcap
+    pub fn accept_all(linktype: LinkType) -> Result<Self, BpfJitError> {
+        BpfJit::ret(linktype, 65535)
+    }
+
+    /// Builds a filter for packets of the given link-layer type that rejects every packet,
+    /// without going through libpcap
+    pub fn reject_all(linktype: LinkType) -> Result<Self, BpfJitError> {
+        BpfJit::ret(linktype, 0)
+    }
+
+    // a single `ret #k`
+    fn ret(linktype: LinkType, k: u32) -> Result<Self, BpfJitError> {
+        let insn = bpf_insn_t {
+            code: opcode::BPF_RET | opcode::BPF_K,
+            jt: 0,
+            jf: 0,
+            k,
+        };
+        let mut result = BpfJit::from_insns(&[insn])?;
+        result.linktype = Some(linktype);
+        Ok(result)
+    }
+
+    /// Combines this filter with `other` into a single program that only accepts packets both
+    /// of them accept, returning the accept length of `other`
+    ///
+    /// Returns `BpfJitError::LinktypeMismatch` if the filters were compiled for different
+    /// link-layer types.
+    pub fn and(&self, other: &BpfJit) -> Result<BpfJit, BpfJitError> {
+        self.check_linktype(other)?;
+        let insns = combine::and(self.instructions(), other.instructions())?;
+        self.combined(&insns, other, "and")
+    }
 
-            drop(lock);
+    /// Combines this filter with `other` into a single program that accepts packets either of
+    /// them accepts, returning the accept length of whichever one accepted it
+    ///
+    /// Returns `BpfJitError::LinktypeMismatch` if the filters were compiled for different
+    /// link-layer types.
+    pub fn or(&self, other: &BpfJit) -> Result<BpfJit, BpfJitError> {
+        self.check_linktype(other)?;
+        let insns = combine::or(self.instructions(), other.instructions())?;
+        self.combined(&insns, other, "or")
+    }
 
-            if compiled != 0 {
-                return Err(Box::from(format!(
-                    "could not compile cBPF expression: {}",
-                    ffi::CStr::from_ptr(pcap_geterr(pcap)).to_str().unwrap()
-                )));
+    /// Returns a program that accepts the packets this filter rejects, and vice versa
+    ///
+    /// This swaps the accepting and rejecting `ret` instructions of the program, rather than
+    /// inverting the expression it was compiled from, so packets this filter would have rejected
+    /// are accepted with the longest accept length it returns anywhere. Packets that are too
+    /// short for the loads the program makes are still rejected.
+    pub fn negate(&self) -> Result<BpfJit, BpfJitError> {
+        let mut result = BpfJit::from_insns(&combine::negate(self.instructions())?)?;
+        if !self.source.is_empty() {
+            result.source = format!("not ({})", self.source);
+        }
+        result.linktype = self.linktype;
+        #[cfg(feature = "pcap")]
+        {
+            result.options = self.options;
+        }
+        Ok(result)
+    }
+
+    // refuses to combine filters that were compiled for different link-layer types
+    fn check_linktype(&self, other: &BpfJit) -> Result<(), BpfJitError> {
+        match (self.linktype, other.linktype) {
+            (Some(first), Some(second)) if first != second => {
+                Err(BpfJitError::LinktypeMismatch(first, second))
             }
+            _ => Ok(()),
+        }
+    }
 
-            result.cb = bpfjit_generate_code(
-                result.ctx,
-                result.prog.bf_insns,
-                result.prog.bf_len as libc::size_t,
+    // JITs the combination of this filter and `other`, keeping the link-layer type and compile
+    // options if they agree
+    fn combined(
+        &self,
+        insns: &[bpf_insn_t],
+        other: &BpfJit,
+        operator: &str,
+    ) -> Result<BpfJit, BpfJitError> {
+        let mut result = BpfJit::from_insns(insns)?;
+        if !self.source.is_empty() && !other.source.is_empty() {
+            result.source = format!("({}) {} ({})", self.source, operator, other.source);
+        }
+        if self.linktype == other.linktype {
+            result.linktype = self.linktype;
+        }
+        #[cfg(feature = "pcap")]
+        if self.options == other.options {
+            result.options = self.options;
+        }
+        Ok(result)
+    }
+
+    /// Copies the filter and JITs the copy, like `clone()` does, but returns
+    /// `BpfJitError::Jit` instead of panicking if the copy can't be JIT'ed
+    pub fn try_clone(&self) -> Result<Self, BpfJitError> {
+        unsafe {
+            // each instance owns (and eventually frees) its own copy of the instructions
+            #[cfg_attr(not(feature = "pcap"), allow(unused_mut))]
+            let mut result = BpfJit::from_program(
+                bpf_program_t::from_insns(self.instructions()),
+                self.ctx.clone(),
+                self.source.clone(),
+                self.linktype,
+                self.is_jit(),
             );
-            if result.cb.is_none() {
-                return Err(Box::from("could not JIT cBPF expression"));
+            #[cfg(feature = "pcap")]
+            {
+                result.options = self.options;
+            }
+            if self.is_jit() && !result.is_jit() {
+                return Err(BpfJitError::Jit);
             }
 
             Ok(result)
         }
     }
 
+    /// Wraps the filter in an `Arc`, to share it between threads
+    ///
+    /// Matching only reads the generated code and keeps its state on the caller's stack, so
+    /// any number of threads can use the same filter at once. Prefer this to `clone()`, which
+    /// copies the program and JITs it again.
+    pub fn shared(self) -> std::sync::Arc<BpfJit> {
+        std::sync::Arc::new(self)
+    }
+
+    /// Returns whether the packet matches the filter
+    ///
+    /// Programs reject packets that are too short for the loads they make instead of reading
+    /// past the end of `data`, so any slice is safe to match, including an empty one.
     pub fn matches(&self, data: &[u8]) -> bool {
+        self.filter_len(data) != 0
+    }
+
+    /// Returns whether the packet matches the filter, like `matches()`, but takes any byte
+    /// buffer that implements `AsRef<[u8]>` by value, e.g. a `Vec<u8>`, `[u8; N]` or
+    /// `bytes::Bytes`
+    pub fn matches_buf<T: AsRef<[u8]>>(&self, data: T) -> bool {
+        self.matches(data.as_ref())
+    }
+
+    /// Runs the filter and returns how many bytes of the packet it accepts, which is zero if
+    /// the packet does not match
+    pub fn filter_len(&self, data: &[u8]) -> u32 {
+        unsafe { self.run(data.as_ptr(), data.len(), data.len()) }
+    }
+
+    /// Matches a packet that was truncated when it was captured, where `data` holds the
+    /// captured bytes and `wirelen` is the length of the original packet on the wire
+    ///
+    /// Panics if `wirelen` is less than `data.len()`.
+    pub fn matches_truncated(&self, data: &[u8], wirelen: usize) -> bool {
+        assert!(
+            wirelen >= data.len(),
+            "wirelen is less than the captured length"
+        );
+        unsafe { self.run(data.as_ptr(), data.len(), wirelen) != 0 }
+    }
+
+    /// Matches a packet as if it had been captured live with the given snaplen, so that the
+    /// filter only sees the first `snaplen` bytes of `data`
+    pub fn matches_with_snaplen(&self, data: &[u8], snaplen: usize) -> bool {
+        let buflen = data.len().min(snaplen);
+        unsafe { self.run(data.as_ptr(), buflen, data.len()) != 0 }
+    }
+
+    /// Matches a packet with a built-in interpreter that gives up with
+    /// `BpfJitError::StepLimitExceeded` after executing `max_steps` instructions, as a safety
+    /// valve for running untrusted programs
+    ///
+    /// cBPF programs can only jump forwards, so no program runs for more steps than it has
+    /// instructions. Programs that call coprocessor functions can't be interpreted, and are
+    /// rejected with `BpfJitError::InvalidProgram`.
+    pub fn matches_bounded(&self, data: &[u8], max_steps: usize) -> Result<bool, BpfJitError> {
+        let wirelen = data.len() as u32;
+        Ok(interp::run(self.instructions(), data, wirelen, max_steps, None)? != 0)
+    }
+
+    /// Matches a packet with the built-in interpreter, recording which instructions were
+    /// executed and which `ret` decided the result, e.g. to find out which alternative of a
+    /// combined filter accepted a packet
+    ///
+    /// Like `matches_bounded()`, this rejects programs that call coprocessor functions with
+    /// `BpfJitError::InvalidProgram`.
+    pub fn explain(&self, data: &[u8]) -> Result<MatchTrace, BpfJitError> {
+        let insns = self.instructions();
+        let mut steps = Vec::new();
+        let value = interp::run(
+            insns,
+            data,
+            data.len() as u32,
+            insns.len(),
+            Some(&mut steps),
+        )?;
+        let ret = steps
+            .last()
+            .copied()
+            .filter(|&n| opcode::class(insns[n].code) == opcode::BPF_RET);
+        Ok(MatchTrace { steps, ret, value })
+    }
+
+    /// Matches the packet that starts `skip` bytes into `data`, e.g. the inner packet of a
+    /// tunnel after its outer headers, as if it had been captured on its own
+    ///
+    /// Panics if `skip` is past the end of `data`.
+    pub fn matches_skipping(&self, data: &[u8], skip: usize) -> bool {
+        match data.get(skip..) {
+            Some(inner) => self.matches(inner),
+            None => panic!("cannot skip {} bytes of a {}-byte packet", skip, data.len()),
+        }
+    }
+
+    /// Matches the `len` bytes of `buf` starting at `offset`, e.g. a packet inside a larger
+    /// capture buffer, without copying them
+    ///
+    /// Panics if the window extends past the end of `buf`.
+    pub fn matches_at(&self, buf: &[u8], offset: usize, len: usize) -> bool {
+        let end = offset.checked_add(len).filter(|&end| end <= buf.len());
+        match end {
+            Some(end) => self.matches(&buf[offset..end]),
+            None => panic!(
+                "packet at offset {} with length {} is outside the {}-byte buffer",
+                offset,
+                len,
+                buf.len()
+            ),
+        }
+    }
+
+    /// Matches a packet read with the `pcap` crate, which may have been truncated when it was
+    /// captured
+    #[cfg(feature = "pcap-interop")]
+    pub fn matches_packet(&self, pkt: &pcap::Packet) -> bool {
+        // a corrupt capture file can claim that the packet was shorter than what was captured
+        let wirelen = (pkt.header.len as usize).max(pkt.data.len());
+        self.matches_truncated(pkt.data, wirelen)
+    }
+
+    /// Matches the `buflen` captured bytes at `pkt` against the filter, without first building
+    /// a slice, where `wirelen` is the length of the original packet on the wire
+    ///
+    /// # Safety
+    ///
+    /// `pkt` must be valid for reads of `buflen` bytes for the duration of the call, and
+    /// `wirelen` must not be less than `buflen`.
+    pub unsafe fn matches_raw(&self, pkt: *const u8, buflen: usize, wirelen: usize) -> bool {
+        self.run(pkt, buflen, wirelen) != 0
+    }
+
+    /// Matches a packet, passing `arg` through to any coprocessor functions the program calls
+    /// as `args.arg`
+    ///
+    /// The pointer is otherwise unused, so it only needs to be valid for whatever the
+    /// coprocessor functions do with it, and only for the duration of the call.
+    pub fn matches_with_arg(&self, data: &[u8], arg: *mut ffi::c_void) -> bool {
         unsafe {
             let mut bpf_args: bpf_args_t = mem::zeroed();
             bpf_args.pkt = data.as_ptr();
             bpf_args.wirelen = data.len();
             bpf_args.buflen = data.len();
+            bpf_args.arg = arg;
+
+            self.call(&mut bpf_args) != 0
+        }
+    }
+
+    /// Matches each packet in turn, returning whether each one matched
+    pub fn matches_many<'a, I: IntoIterator<Item = &'a [u8]>>(&self, packets: I) -> Vec<bool> {
+        unsafe {
+            let mut bpf_args: bpf_args_t = mem::zeroed();
+            packets
+                .into_iter()
+                .map(|data| {
+                    bpf_args.pkt = data.as_ptr();
+                    bpf_args.wirelen = data.len();
+                    bpf_args.buflen = data.len();
+                    self.call(&mut bpf_args) != 0
+                })
+                .collect()
+        }
+    }
+
+    /// Returns an iterator over just the packets that match
+    pub fn filter_iter<'a, I>(&'a self, packets: I) -> impl Iterator<Item = I::Item> + 'a
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+        I::IntoIter: 'a,
+    {
+        packets.into_iter().filter(move |data| self.matches(data))
+    }
+
+    /// Returns how many of the packets match, without allocating a result for each one
+    pub fn count_matches<'a, I: IntoIterator<Item = &'a [u8]>>(&self, packets: I) -> usize {
+        unsafe {
+            let mut bpf_args: bpf_args_t = mem::zeroed();
+            packets
+                .into_iter()
+                .filter(|data| {
+                    bpf_args.pkt = data.as_ptr();
+                    bpf_args.wirelen = data.len();
+                    bpf_args.buflen = data.len();
+                    self.call(&mut bpf_args) != 0
+                })
+                .count()
+        }
+    }
 
-            self.cb.unwrap()(self.ctx, &mut bpf_args) != 0
+    unsafe fn run(&self, pkt: *const u8, buflen: usize, wirelen: usize) -> libc::c_uint {
+        let mut bpf_args: bpf_args_t = mem::zeroed();
+        bpf_args.pkt = pkt;
+        bpf_args.wirelen = wirelen;
+        bpf_args.buflen = buflen;
+
+        self.call(&mut bpf_args)
+    }
+
+    unsafe fn call(&self, bpf_args: &mut bpf_args_t) -> libc::c_uint {
+        // the external memory store, if there is one, which starts out zeroed on every call so
+        // that nothing leaks from one packet to the next
+        let mut mem = [0u32; BPF_MEMWORDS];
+        bpf_args.mem = mem.as_mut_ptr();
+
+        self.dispatch(bpf_args)
+    }
+
+    // runs the program with arguments whose memory store (if it needs one) is already set up
+    pub(crate) unsafe fn dispatch(&self, bpf_args: &mut bpf_args_t) -> libc::c_uint {
+        match self.cb {
+            Some(cb) => cb(self.ctx_ptr(), bpf_args),
+            #[cfg(feature = "pcap")]
+            None => bpf_filter(
+                self.prog.bf_insns,
+                bpf_args.pkt,
+                bpf_args.wirelen as libc::c_uint,
+                bpf_args.buflen as libc::c_uint,
+            ),
+            // without libpcap every constructor rejects programs that can't be JIT'ed, so this
+            // can't happen, but rejecting the packet is still better than panicking
+            #[cfg(not(feature = "pcap"))]
+            None => 0,
         }
     }
+
+    fn ctx_ptr(&self) -> *const bpf_ctx_t {
+        self.ctx.as_ref().map_or(ptr::null(), |ctx| &ctx.raw)
+    }
+
+    // the number of words in the external memory store, if there is one
+    pub(crate) fn extwords(&self) -> usize {
+        self.ctx.as_ref().map_or(0, |ctx| ctx.raw.extwords)
+    }
+
+    /// Returns a `Matcher` that reuses the same arguments and memory store for every packet
+    pub fn matcher(&self) -> Matcher<'_> {
+        Matcher::new(self)
+    }
+
+    /// Returns whether matching runs natively generated code, rather than libpcap's interpreter
+    ///
+    /// Without the `pcap` feature there is no interpreter, so this is always true.
+    pub fn is_jit(&self) -> bool {
+        self.cb.is_some()
+    }
+
+    /// Returns the filter expression this was compiled from, which is empty for programs that
+    /// were loaded from raw instructions
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the link-layer type the filter was compiled for, which is unknown for programs
+    /// that were loaded from raw instructions
+    pub fn linktype(&self) -> Option<LinkType> {
+        self.linktype
+    }
+
+    /// Returns the compiled cBPF program
+    ///
+    /// The slice borrows the buffer this filter owns and frees when dropped, so the raw program
+    /// is never handed out by value; use `to_vec()` to keep the instructions around for longer.
+    pub fn instructions(&self) -> &[bpf_insn_t] {
+        unsafe { self.prog.insns() }
+    }
+
+    /// Returns whether the compiled program is exactly `other`, e.g. the output of `tcpdump -dd`
+    /// for the same expression
+    ///
+    /// libpcap's optimizer changes between releases, so only compare against dumps made with
+    /// the same libpcap version, and the same optimizer setting (`tcpdump -O` disables it).
+    pub fn equals_bytecode(&self, other: &[bpf_insn_t]) -> bool {
+        self.instructions() == other
+    }
+
+    /// Returns whether the program accepts every packet, as the empty filter does, so that
+    /// callers can skip matching altogether
+    pub fn is_match_all(&self) -> bool {
+        match self.instructions() {
+            [insn] => insn.code == opcode::BPF_RET | opcode::BPF_K && insn.k != 0,
+            _ => false,
+        }
+    }
+
+    /// Returns how many bytes of the packet the program's absolute loads (e.g. `ether[14]`)
+    /// reach, i.e. the largest offset loaded from plus the width of that load, or `None` if it
+    /// has none
+    ///
+    /// Indexed loads (e.g. of a TCP port, whose offset depends on the IP header length) can't
+    /// be bounded statically and aren't taken into account.
+    pub fn max_access_offset(&self) -> Option<u32> {
+        use opcode::*;
+        self.instructions()
+            .iter()
+            .filter_map(|insn| {
+                let width = match insn.code {
+                    c if c == BPF_LD | BPF_W | BPF_ABS => 4,
+                    c if c == BPF_LD | BPF_H | BPF_ABS => 2,
+                    c if c == BPF_LD | BPF_B | BPF_ABS => 1,
+                    c if c == BPF_LDX | BPF_B | BPF_MSH => 1,
+                    _ => return None,
+                };
+                Some(insn.k.saturating_add(width))
+            })
+            .max()
+    }
+
+    /// Checks that the filter can accept packets captured with the snaplen it was compiled
+    /// for, and returns `BpfJitError::OffsetBeyondSnaplen` if every way it has of accepting a
+    /// packet loads bytes past the snaplen, in which case it rejects every captured packet
+    ///
+    /// Like `max_access_offset()`, this only looks at absolute loads. Filters that weren't
+    /// compiled from an expression have no snaplen, and always pass.
+    pub fn validate_against_snaplen(&self) -> Result<(), BpfJitError> {
+        #[cfg(feature = "pcap")]
+        let snaplen = self.options.map(|options| options.snaplen);
+        #[cfg(not(feature = "pcap"))]
+        let snaplen = None;
+
+        match (stats::accept_reach(self.instructions()), snaplen) {
+            (Some(offset), Some(snaplen)) if i64::from(offset) > i64::from(snaplen) => {
+                Err(BpfJitError::OffsetBeyondSnaplen { offset, snaplen })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Summarizes the compiled program, e.g. how many jumps and loads it makes
+    pub fn stats(&self) -> ProgramStats {
+        stats::stats(self.instructions())
+    }
+
+    /// Returns whether the program rejects every packet, as contradictory filters like
+    /// `ip and not ip` do once optimized, which usually means the filter is misconfigured
+    pub fn is_reject_all(&self) -> bool {
+        match self.instructions() {
+            [insn] => insn.code == opcode::BPF_RET | opcode::BPF_K && insn.k == 0,
+            _ => false,
+        }
+    }
+
+    /// Returns the number of instructions in the compiled program, a rough measure of how
+    /// expensive the filter is to run
+    pub fn instruction_count(&self) -> usize {
+        self.prog.bf_len as usize
+    }
+
+    /// Formats the compiled program as a C array fragment, like `tcpdump -dd` does
+    pub fn to_cc(&self) -> String {
+        let mut result = String::new();
+        for insn in self.instructions() {
+            result.push_str(&format!(
+                "{{ 0x{:x}, {}, {}, 0x{:08x} }},\n",
+                insn.code, insn.jt, insn.jf, insn.k
+            ));
+        }
+        result
+    }
+
+    /// Serializes the compiled program in a portable format that can be loaded back with
+    /// `from_bytes()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialize::to_bytes(self.instructions())
+    }
+
+    /// Returns the compiled program as the instructions of a Linux socket filter, e.g. for
+    /// attaching to a socket with `SO_ATTACH_FILTER`
+    pub fn to_sock_filter(&self) -> Vec<SockFilter> {
+        self.instructions()
+            .iter()
+            .map(|&insn| SockFilter::from(insn))
+            .collect()
+    }
+
+    /// Returns the compiled program as a `struct sock_fprog` that owns its instructions, for
+    /// passing to `setsockopt(SO_ATTACH_FILTER)` directly
+    ///
+    /// Fails with `io::ErrorKind::InvalidInput` if the program has more than 65535
+    /// instructions.
+    #[cfg(target_os = "linux")]
+    pub fn to_sock_fprog(&self) -> std::io::Result<SockFprog> {
+        SockFprog::new(self.to_sock_filter())
+    }
+
+    /// Attaches the compiled program to the socket `fd` as a kernel socket filter, so that the
+    /// kernel drops packets that don't match before they are ever received
+    #[cfg(target_os = "linux")]
+    pub fn attach_to_fd(&self, fd: std::os::unix::io::RawFd) -> std::io::Result<()> {
+        socket::attach(fd, &self.to_sock_fprog()?)
+    }
+
+    /// Attaches the compiled program to `sock` as a kernel socket filter, like `attach_to_fd()`
+    #[cfg(all(target_os = "linux", feature = "socket2-interop"))]
+    pub fn attach_to_socket(&self, sock: &socket2::Socket) -> std::io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        self.attach_to_fd(sock.as_raw_fd())
+    }
+
+    /// Formats the compiled program as human-readable assembly, like `tcpdump -d` does
+    pub fn disassemble(&self) -> String {
+        let mut result = String::new();
+        self.disassemble_into(&mut result);
+        result
+    }
+
+    /// Appends the output of `disassemble()` to `buf`, e.g. to reuse the same buffer when
+    /// logging many filters
+    pub fn disassemble_into(&self, buf: &mut String) {
+        for (n, insn) in self.instructions().iter().enumerate() {
+            buf.push_str(&disasm::image(insn, n));
+            buf.push('\n');
+        }
+    }
+
+    /// Formats each instruction of the compiled program with libpcap's own `bpf_image()`,
+    /// exactly as `tcpdump -d` prints it
+    #[cfg(feature = "pcap")]
+    pub fn image(&self) -> Vec<String> {
+        // bpf_image() formats into a static buffer, which is only safe to read until the next
+        // call, so calls are serialized and each result is copied out at once
+        let _lock = biglock();
+        self.instructions()
+            .iter()
+            .enumerate()
+            .map(|(n, insn)| unsafe {
+                ffi::CStr::from_ptr(bpf_image(insn, n as libc::c_int))
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect()
+    }
+
+    /// Prints the compiled program to stdout, like `tcpdump -dd` does
+    pub fn print_bpf(&self) {
+        print!("{}", self.to_cc());
+    }
 }
 
 impl Clone for BpfJit {
     fn clone(&self) -> Self {
-        unsafe {
-            let mut result: BpfJit = mem::zeroed();
+        // we already JIT'ed the same program before, so this should never fail
+        self.try_clone().expect("could not JIT cBPF expression")
+    }
+}
 
-            result.prog = self.prog;
+impl TryFrom<&[bpf_insn_t]> for BpfJit {
+    type Error = BpfJitError;
 
-            result.cb = bpfjit_generate_code(
-                result.ctx,
-                result.prog.bf_insns,
-                result.prog.bf_len as libc::size_t,
-            );
-            if result.cb.is_none() {
-                panic!("could not JIT cBPF expression"); // we already JIT'ed the same program before, so this should never happen
-            }
+    fn try_from(insns: &[bpf_insn_t]) -> Result<Self, Self::Error> {
+        BpfJit::from_insns(insns)
+    }
+}
+
+impl TryFrom<Vec<bpf_insn_t>> for BpfJit {
+    type Error = BpfJitError;
+
+    // the instructions are still copied, since pcap_freecode() needs a malloc()'d buffer
+    fn try_from(insns: Vec<bpf_insn_t>) -> Result<Self, Self::Error> {
+        BpfJit::from_insns(&insns)
+    }
+}
+
+impl From<&BpfJit> for Vec<bpf_insn_t> {
+    fn from(filter: &BpfJit) -> Self {
+        filter.instructions().to_vec()
+    }
+}
+
+// programs are equal if they have the same instructions, however they were compiled
+impl PartialEq for BpfJit {
+    fn eq(&self, other: &Self) -> bool {
+        self.instructions() == other.instructions()
+    }
+}
+
+impl Eq for BpfJit {}
+
+impl hash::Hash for BpfJit {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.instructions().hash(state);
+    }
+}
 
-            result
+impl fmt::Debug for BpfJit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BpfJit")
+            .field("source", &self.source)
+            .field("linktype", &self.linktype)
+            .field("instructions", &self.prog.bf_len)
+            .field("jit", &self.is_jit())
+            .finish()
+    }
+}
+
+impl fmt::Display for BpfJit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.source.is_empty() {
+            writeln!(f, "{}", self.source)?;
         }
+        write!(f, "{}", self.disassemble())
     }
 }
 
@@ -209,6 +1443,9 @@ impl Drop for BpfJit {
             if self.cb.is_some() {
                 bpfjit_free_code(self.cb);
             }
+            if !self.prog.bf_insns.is_null() {
+                self.prog.free();
+            }
         }
     }
 }
@@ -216,3 +1453,54 @@ impl Drop for BpfJit {
 unsafe impl Send for BpfJit {}
 
 unsafe impl Sync for BpfJit {}
+
+#[cfg(all(test, feature = "pcap"))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_recompile_keeps_context() -> Result<(), BpfJitError> {
+        extern "C" fn answer(_: *const bpf_ctx_t, _: *mut bpf_args_t, _: u32) -> u32 {
+            42
+        }
+
+        let ret = bpf_insn_t {
+            code: opcode::BPF_RET | opcode::BPF_K,
+            jt: 0,
+            jf: 0,
+            k: 1,
+        };
+        let ctx = BpfContext::new().extwords(4).copfuncs(&[answer]);
+        let mut filter = BpfJit::from_insns_with_context(&[ret], ctx)?;
+        filter.recompile("udp dst port 123")?;
+
+        let ctx = filter.ctx.as_ref().expect("the context was dropped");
+        assert_eq!(ctx.raw.nfuncs, 1);
+        assert_eq!(filter.extwords(), 4);
+        assert!(filter.is_jit());
+        assert_eq!(filter.source(), "udp dst port 123");
+        assert_eq!(filter.linktype(), Some(LinkType::Ethernet));
+        assert!(!filter.matches(&[]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_biglock_poisoned() -> Result<(), BpfJitError> {
+        let panicked = thread::spawn(|| {
+            let _lock = BIGLOCK.lock().unwrap();
+            panic!("panic while holding the lock");
+        })
+        .join();
+        assert!(panicked.is_err());
+        assert!(BIGLOCK.is_poisoned());
+
+        // compiles only take the lock with libpcap < 1.8, so each user is checked on its own
+        drop(biglock());
+        let filter = BpfJit::new("udp")?;
+        assert_eq!(filter.image().len(), filter.instruction_count());
+        assert!(!filter.matches(&[0u8; 14][..]));
+        assert!(BIGLOCK.is_poisoned());
+        Ok(())
+    }
+}
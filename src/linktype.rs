@@ -0,0 +1,62 @@
+// src/linktype.rs
+
+/// Link-layer header types understood by libpcap's filter compiler
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkType {
+    /// BSD loopback encapsulation (`DLT_NULL`)
+    Null,
+    /// Ethernet (`DLT_EN10MB`)
+    Ethernet,
+    /// raw IPv4 or IPv6 with no link-layer header (`DLT_RAW`)
+    RawIp,
+    /// IEEE 802.11 wireless (`DLT_IEEE802_11`)
+    Ieee80211,
+    /// OpenBSD loopback encapsulation (`DLT_LOOP`)
+    Loopback,
+    /// Linux "cooked" capture (`DLT_LINUX_SLL`)
+    LinuxSll,
+    /// IEEE 802.11 with a radiotap header (`DLT_IEEE802_11_RADIO`)
+    Radiotap,
+    /// raw IPv6 with no link-layer header (`DLT_IPV6`)
+    Ipv6,
+    /// Linux "cooked" capture, version 2 (`DLT_LINUX_SLL2`)
+    LinuxSll2,
+    /// any other `DLT_*` value, for link-layer types libpcap supports that have no variant of
+    /// their own above
+    Other(i32),
+}
+
+impl LinkType {
+    /// Returns the libpcap `DLT_*` value for this link-layer type
+    pub fn as_dlt(&self) -> i32 {
+        match self {
+            LinkType::Null => 0,
+            LinkType::Ethernet => 1,
+            LinkType::RawIp => 12,
+            LinkType::Ieee80211 => 105,
+            LinkType::Loopback => 108,
+            LinkType::LinuxSll => 113,
+            LinkType::Radiotap => 127,
+            LinkType::Ipv6 => 229,
+            LinkType::LinuxSll2 => 276,
+            LinkType::Other(dlt) => *dlt,
+        }
+    }
+
+    /// Returns the link-layer type for a libpcap `DLT_*` value, if it is one with a variant of
+    /// its own (other values can still be used as `LinkType::Other`)
+    pub fn from_dlt(dlt: i32) -> Option<LinkType> {
+        match dlt {
+            0 => Some(LinkType::Null),
+            1 => Some(LinkType::Ethernet),
+            12 => Some(LinkType::RawIp),
+            105 => Some(LinkType::Ieee80211),
+            108 => Some(LinkType::Loopback),
+            113 => Some(LinkType::LinuxSll),
+            127 => Some(LinkType::Radiotap),
+            229 => Some(LinkType::Ipv6),
+            276 => Some(LinkType::LinuxSll2),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+// src/matcher.rs
+
+use std::ptr;
+
+use crate::{bpf_args_t, BpfJit, BPF_MEMWORDS};
+
+/// Matches packets against a filter, reusing the same arguments and memory store every time
+///
+/// `BpfJit::matches()` sets up fresh arguments and zeroes a whole memory store for every
+/// packet. A `Matcher` does that once, and then only zeroes the words of the external memory
+/// store the filter actually has, if any.
+pub struct Matcher<'a> {
+    filter: &'a BpfJit,
+    args: bpf_args_t,
+    mem: [u32; BPF_MEMWORDS],
+}
+
+impl<'a> Matcher<'a> {
+    pub(crate) fn new(filter: &'a BpfJit) -> Self {
+        Matcher {
+            filter,
+            args: bpf_args_t {
+                pkt: ptr::null(),
+                wirelen: 0,
+                buflen: 0,
+                mem: ptr::null_mut(),
+                arg: ptr::null_mut(),
+            },
+            mem: [0; BPF_MEMWORDS],
+        }
+    }
+
+    /// Runs the filter and returns how many bytes of the packet it accepts, which is zero if
+    /// the packet does not match
+    pub fn run(&mut self, data: &[u8]) -> u32 {
+        self.mem[..self.filter.extwords()]
+            .iter_mut()
+            .for_each(|word| *word = 0);
+        self.args.pkt = data.as_ptr();
+        self.args.wirelen = data.len();
+        self.args.buflen = data.len();
+        // set on every call, since the matcher may have moved since the last one
+        self.args.mem = self.mem.as_mut_ptr();
+
+        unsafe { self.filter.dispatch(&mut self.args) }
+    }
+
+    /// Returns whether the packet matches the filter
+    pub fn matches(&mut self, data: &[u8]) -> bool {
+        self.run(data) != 0
+    }
+}
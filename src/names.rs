@@ -0,0 +1,101 @@
+// src/names.rs
+
+// the words libpcap's scanner knows, which are never looked up as names
+#[rustfmt::skip]
+const KEYWORDS: &[&str] = &[
+    // qualifiers and operators
+    "host", "net", "mask", "port", "portrange", "proto", "protochain", "gateway", "src", "dst",
+    "and", "or", "not", "less", "greater", "len", "byte", "broadcast", "multicast", "inbound",
+    "outbound", "ifindex", "type", "subtype", "direction", "dir", "address1", "addr1", "address2",
+    "addr2", "address3", "addr3", "address4", "addr4", "ra", "ta",
+    // protocols
+    "link", "data-link", "ether", "fddi", "tr", "token-ring", "wlan", "ppp", "slip", "arp", "rarp",
+    "ip", "sctp", "tcp", "udp", "icmp", "igmp", "igrp", "pim", "vrrp", "carp", "radio", "ip6",
+    "icmp6", "ah", "esp", "atalk", "aarp", "decnet", "lat", "sca", "moprc", "mopdl", "iso", "esis",
+    "es-is", "isis", "is-is", "l1", "l2", "iih", "lsp", "snp", "csnp", "psnp", "clnp", "stp", "ipx",
+    "netbeui", "vlan", "mpls", "pppoed", "pppoes", "geneve", "llc",
+    // ATM, pflog and SS7 primitives
+    "lane", "metac", "bcc", "oam", "oamf4", "oamf4ec", "oamf4sc", "sc", "ilmic", "vpi", "vci",
+    "connectmsg", "metaconnect", "on", "ifname", "rnr", "rulenum", "reason", "rset", "srnr",
+    "subrulenum", "action", "fisu", "lssu", "lsu", "msu", "hfisu", "hlssu", "hmsu", "sio", "opc",
+    "dpc", "sls", "hsio", "hopc", "hdpc", "hsls",
+    // constants for header fields
+    "tcpflags", "tcp-fin", "tcp-syn", "tcp-rst", "tcp-push", "tcp-ack", "tcp-urg", "tcp-ece",
+    "tcp-cwr", "icmptype", "icmpcode", "icmp-echoreply", "icmp-unreach", "icmp-sourcequench",
+    "icmp-redirect", "icmp-echo", "icmp-routeradvert", "icmp-routersolicit", "icmp-timxceed",
+    "icmp-paramprob", "icmp-tstamp", "icmp-tstampreply", "icmp-ireq", "icmp-ireqreply",
+    "icmp-maskreq", "icmp-maskreply", "icmp6type", "icmp6code", "icmp6-destinationunreach",
+    "icmp6-packettoobig", "icmp6-timeexceeded", "icmp6-parameterproblem", "icmp6-echo",
+    "icmp6-echoreply", "icmp6-multicastlistenerquery", "icmp6-multicastlistenerreportv1",
+    "icmp6-multicastlistenerdone", "icmp6-routersolicit", "icmp6-routeradvert",
+    "icmp6-neighborsolicit", "icmp6-neighboradvert", "icmp6-redirect", "icmp6-routerrenum",
+    "icmp6-nodeinformationquery", "icmp6-nodeinformationresponse",
+    "icmp6-ineighbordiscoverysolicit", "icmp6-ineighbordiscoveryadvert",
+    "icmp6-multicastlistenerreportv2", "icmp6-homeagentdiscoveryrequest",
+    "icmp6-homeagentdiscoveryreply", "icmp6-mobileprefixsolicit", "icmp6-mobileprefixadvert",
+    "icmp6-certpathsolicit", "icmp6-certpathadvert", "icmp6-multicastrouteradvert",
+    "icmp6-multicastroutersolicit", "icmp6-multicastrouterterm",
+];
+
+// keywords whose operand is a port, protocol, frame type or interface, rather than a host
+#[rustfmt::skip]
+const NON_HOST_OPERANDS: &[&str] = &[
+    "port", "portrange", "proto", "protochain", "type", "subtype", "dir", "direction", "llc",
+    "reason", "action", "ifname", "on",
+];
+
+// characters that separate words in an expression; `-` and `.` are part of names
+const SEPARATORS: &str = "()[]!&|=<>+*/%^,";
+
+// whether `word` is a number or an address, rather than a name
+fn is_address(word: &str) -> bool {
+    let is_hex = |digits: &str| digits.chars().all(|c| c.is_ascii_hexdigit());
+    // MAC addresses, in any of the forms libpcap's scanner accepts
+    let is_mac = |separator, groups: usize, width: std::ops::RangeInclusive<usize>| {
+        let parts: Vec<&str> = word.split(separator).collect();
+        parts.len() == groups
+            && parts
+                .iter()
+                .all(|part| width.contains(&part.len()) && is_hex(part))
+    };
+    let hex = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X"));
+
+    word.chars().all(|c| c.is_ascii_digit() || c == '.')
+        || matches!(hex, Some(digits) if is_hex(digits))
+        // IPv6 addresses, and MAC addresses with colons
+        || word.contains(':')
+        || is_mac('-', 6, 1..=2)
+        || is_mac('.', 6, 1..=2)
+        || is_mac('.', 3, 4..=4)
+        || (word.len() == 12 && is_hex(word))
+}
+
+// returns the first word of `filter` that libpcap would look up as a host or network name
+// (possibly with a blocking DNS query) while compiling it
+//
+// libpcap assumes `host` when a primitive has no qualifier, and carries the last primitive's
+// qualifiers across `and`, `or` and `not`, so any word that isn't a keyword, an address, or
+// an operand while the current qualifier doesn't take hosts is taken to be a name
+pub(crate) fn find_name(filter: &str) -> Option<&str> {
+    let mut qualifier = "";
+    let words = filter
+        .split(|c: char| c.is_whitespace() || SEPARATORS.contains(c))
+        .filter(|word| !word.is_empty());
+    for word in words {
+        if KEYWORDS.contains(&word) {
+            // any other keyword starts a new primitive, whose qualifiers replace the old ones
+            if !["and", "or", "not"].contains(&word) {
+                qualifier = word;
+            }
+            continue;
+        }
+        let is_name = word.contains(|c: char| c.is_ascii_alphanumeric())
+            && !word.starts_with('\\')
+            && !is_address(word)
+            && !NON_HOST_OPERANDS.contains(&qualifier);
+        if is_name {
+            return Some(word);
+        }
+    }
+    None
+}
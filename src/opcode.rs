@@ -0,0 +1,68 @@
+// src/opcode.rs
+
+// opcode fields and values, as defined by pcap-bpf.h
+
+// instruction classes
+pub(crate) const BPF_LD: u16 = 0x00;
+pub(crate) const BPF_LDX: u16 = 0x01;
+pub(crate) const BPF_ST: u16 = 0x02;
+pub(crate) const BPF_STX: u16 = 0x03;
+pub(crate) const BPF_ALU: u16 = 0x04;
+pub(crate) const BPF_JMP: u16 = 0x05;
+pub(crate) const BPF_RET: u16 = 0x06;
+pub(crate) const BPF_MISC: u16 = 0x07;
+
+// ld/ldx operand sizes
+pub(crate) const BPF_W: u16 = 0x00;
+pub(crate) const BPF_H: u16 = 0x08;
+pub(crate) const BPF_B: u16 = 0x10;
+
+// ld/ldx addressing modes
+pub(crate) const BPF_IMM: u16 = 0x00;
+pub(crate) const BPF_ABS: u16 = 0x20;
+pub(crate) const BPF_IND: u16 = 0x40;
+pub(crate) const BPF_MEM: u16 = 0x60;
+pub(crate) const BPF_LEN: u16 = 0x80;
+pub(crate) const BPF_MSH: u16 = 0xa0;
+
+// alu/jmp operations
+pub(crate) const BPF_ADD: u16 = 0x00;
+pub(crate) const BPF_SUB: u16 = 0x10;
+pub(crate) const BPF_MUL: u16 = 0x20;
+pub(crate) const BPF_DIV: u16 = 0x30;
+pub(crate) const BPF_OR: u16 = 0x40;
+pub(crate) const BPF_AND: u16 = 0x50;
+pub(crate) const BPF_LSH: u16 = 0x60;
+pub(crate) const BPF_RSH: u16 = 0x70;
+pub(crate) const BPF_NEG: u16 = 0x80;
+pub(crate) const BPF_MOD: u16 = 0x90;
+pub(crate) const BPF_XOR: u16 = 0xa0;
+
+pub(crate) const BPF_JA: u16 = 0x00;
+pub(crate) const BPF_JEQ: u16 = 0x10;
+pub(crate) const BPF_JGT: u16 = 0x20;
+pub(crate) const BPF_JGE: u16 = 0x30;
+pub(crate) const BPF_JSET: u16 = 0x40;
+
+// alu/jmp operand sources
+pub(crate) const BPF_K: u16 = 0x00;
+pub(crate) const BPF_X: u16 = 0x08;
+
+// ret operand sources
+pub(crate) const BPF_A: u16 = 0x10;
+
+// misc operations
+pub(crate) const BPF_TAX: u16 = 0x00;
+pub(crate) const BPF_TXA: u16 = 0x80;
+
+pub(crate) fn class(code: u16) -> u16 {
+    code & 0x07
+}
+
+pub(crate) fn op(code: u16) -> u16 {
+    code & 0xf0
+}
+
+pub(crate) fn mode(code: u16) -> u16 {
+    code & 0xe0
+}
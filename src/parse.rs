@@ -0,0 +1,98 @@
+// src/parse.rs
+
+use crate::{bpf_insn_t, BpfJitError};
+
+fn parse_number(text: &str) -> Option<u64> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn parse_cc_record(record: &str) -> Option<bpf_insn_t> {
+    let fields = record.strip_prefix('{')?.strip_suffix('}')?;
+    parse_fields(&fields.split(',').collect::<Vec<_>>())
+}
+
+// parses the code, jt, jf and k fields of an instruction
+fn parse_fields(fields: &[&str]) -> Option<bpf_insn_t> {
+    if fields.len() != 4 {
+        return None;
+    }
+
+    let code = parse_number(fields[0])?;
+    let jt = parse_number(fields[1])?;
+    let jf = parse_number(fields[2])?;
+    let k = parse_number(fields[3])?;
+    if code > u16::MAX as u64 || jt > u8::MAX as u64 || jf > u8::MAX as u64 || k > u32::MAX as u64 {
+        return None;
+    }
+
+    Some(bpf_insn_t {
+        code: code as u16,
+        jt: jt as u8,
+        jf: jf as u8,
+        k: k as u32,
+    })
+}
+
+// parses the `{ code, jt, jf, k },` lines printed by `tcpdump -dd`
+pub(crate) fn parse_cc(text: &str) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    let mut insns = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let record = line.trim().trim_end_matches(',').trim_end();
+        // tolerate blank lines and the braces of a surrounding array initializer
+        if record.is_empty() || record == "{" || record == "}" || record == "};" {
+            continue;
+        }
+
+        match parse_cc_record(record) {
+            Some(insn) => insns.push(insn),
+            None => {
+                return Err(BpfJitError::Parse {
+                    line: n + 1,
+                    content: line.to_string(),
+                })
+            }
+        }
+    }
+
+    Ok(insns)
+}
+
+// parses the instruction count and `code jt jf k` lines printed by `tcpdump -ddd`
+pub(crate) fn parse_ddd(text: &str) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    let error = |n: usize, line: &str| BpfJitError::Parse {
+        line: n + 1,
+        content: line.to_string(),
+    };
+    // tolerate blank lines, e.g. a trailing one
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let (count_line, count_text) = lines.next().ok_or_else(|| error(0, ""))?;
+    let count: usize = count_text
+        .trim()
+        .parse()
+        .map_err(|_| error(count_line, count_text))?;
+
+    let mut insns = Vec::with_capacity(count);
+    for (n, line) in lines {
+        match parse_fields(&line.split_whitespace().collect::<Vec<_>>()) {
+            Some(insn) => insns.push(insn),
+            None => return Err(error(n, line)),
+        }
+    }
+
+    // the count must agree with the instructions that follow it
+    if insns.len() != count {
+        return Err(error(count_line, count_text));
+    }
+
+    Ok(insns)
+}
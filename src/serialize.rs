@@ -0,0 +1,46 @@
+// src/serialize.rs
+
+use crate::{bpf_insn_t, BpfJitError};
+
+const MAGIC: &[u8; 4] = b"BPFJ";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 9; // magic, version and instruction count
+const INSN_LEN: usize = 8;
+
+// serializes the instructions as the magic and version, followed by the instruction count and
+// then each instruction's code, jt, jf and k fields, all little-endian
+pub(crate) fn to_bytes(insns: &[bpf_insn_t]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(HEADER_LEN + insns.len() * INSN_LEN);
+    result.extend_from_slice(MAGIC);
+    result.push(VERSION);
+    result.extend_from_slice(&(insns.len() as u32).to_le_bytes());
+    for insn in insns {
+        result.extend_from_slice(&insn.code.to_le_bytes());
+        result.push(insn.jt);
+        result.push(insn.jf);
+        result.extend_from_slice(&insn.k.to_le_bytes());
+    }
+    result
+}
+
+pub(crate) fn from_bytes(data: &[u8]) -> Result<Vec<bpf_insn_t>, BpfJitError> {
+    if data.len() < HEADER_LEN || &data[..4] != MAGIC || data[4] != VERSION {
+        return Err(BpfJitError::InvalidFormat);
+    }
+
+    let len = u32::from_le_bytes([data[5], data[6], data[7], data[8]]) as usize;
+    let body = &data[HEADER_LEN..];
+    if len.checked_mul(INSN_LEN) != Some(body.len()) {
+        return Err(BpfJitError::InvalidFormat);
+    }
+
+    Ok(body
+        .chunks_exact(INSN_LEN)
+        .map(|insn| bpf_insn_t {
+            code: u16::from_le_bytes([insn[0], insn[1]]),
+            jt: insn[2],
+            jf: insn[3],
+            k: u32::from_le_bytes([insn[4], insn[5], insn[6], insn[7]]),
+        })
+        .collect())
+}
@@ -0,0 +1,115 @@
+// src/socket.rs
+
+#[cfg(target_os = "linux")]
+use std::fmt;
+#[cfg(target_os = "linux")]
+use std::io;
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::RawFd;
+
+use crate::bpf_insn_t;
+
+/// A single classic BPF instruction, laid out like the Linux kernel's `struct sock_filter`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SockFilter {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl From<bpf_insn_t> for SockFilter {
+    fn from(insn: bpf_insn_t) -> Self {
+        SockFilter {
+            code: insn.code,
+            jt: insn.jt,
+            jf: insn.jf,
+            k: insn.k,
+        }
+    }
+}
+
+/// A `struct sock_fprog` for `setsockopt(SO_ATTACH_FILTER)`, which owns the instructions it
+/// points to, so they stay alive for as long as the struct does
+#[cfg(target_os = "linux")]
+pub struct SockFprog {
+    // boxed so that the instructions don't move when the struct does
+    filter: Box<[SockFilter]>,
+    prog: libc::sock_fprog,
+}
+
+// the only pointer in `prog` is to `filter`, which the struct owns
+#[cfg(target_os = "linux")]
+unsafe impl Send for SockFprog {}
+
+#[cfg(target_os = "linux")]
+impl SockFprog {
+    pub(crate) fn new(filter: Vec<SockFilter>) -> io::Result<Self> {
+        if filter.len() > u16::MAX as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cBPF program is too long for a socket filter",
+            ));
+        }
+
+        let mut filter = filter.into_boxed_slice();
+        let prog = libc::sock_fprog {
+            len: filter.len() as libc::c_ushort,
+            filter: filter.as_mut_ptr() as *mut libc::sock_filter,
+        };
+        Ok(SockFprog { filter, prog })
+    }
+
+    /// Returns the number of instructions, as stored in the `len` field
+    pub fn len(&self) -> u16 {
+        self.prog.len
+    }
+
+    /// Returns whether the program has no instructions, which the kernel would reject
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_empty()
+    }
+
+    /// Returns the instructions the `filter` field points to
+    pub fn filter(&self) -> &[SockFilter] {
+        &self.filter
+    }
+
+    /// Returns a pointer to the `struct sock_fprog`, to pass to `setsockopt()`; it is valid for
+    /// as long as `self` is
+    pub fn as_ptr(&self) -> *const libc::sock_fprog {
+        &self.prog
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl fmt::Debug for SockFprog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SockFprog")
+            .field("len", &self.len())
+            .field("filter", &self.filter)
+            .finish()
+    }
+}
+
+// attaches the filter with setsockopt(SO_ATTACH_FILTER), which copies the instructions
+#[cfg(target_os = "linux")]
+pub(crate) fn attach(fd: RawFd, prog: &SockFprog) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ATTACH_FILTER,
+            prog.as_ptr() as *const libc::c_void,
+            mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
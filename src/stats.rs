@@ -0,0 +1,92 @@
+// src/stats.rs
+
+use crate::bpf_insn_t;
+use crate::opcode::*;
+
+/// A summary of a compiled program, as a rough measure of how expensive it is to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ProgramStats {
+    /// the number of instructions
+    pub instructions: usize,
+    /// the number of jumps, conditional or not
+    pub jumps: usize,
+    /// the number of loads from the packet
+    pub packet_loads: usize,
+    /// the number of loads from and stores to the memory store (`M[]`)
+    pub memory_accesses: usize,
+    /// whether any `ret` accepts fewer than 65535 bytes, or returns the accumulator, and so may
+    /// truncate the packets it accepts
+    pub truncates: bool,
+    /// the largest number of conditional jumps taken on the way to any `ret`
+    pub max_branch_depth: usize,
+}
+
+pub(crate) fn stats(insns: &[bpf_insn_t]) -> ProgramStats {
+    let mut stats = ProgramStats {
+        instructions: insns.len(),
+        ..ProgramStats::default()
+    };
+
+    // programs only jump forwards, so the depth past each instruction can be worked out from
+    // the end of the program backwards
+    let mut depth = vec![0usize; insns.len() + 1];
+    for (n, insn) in insns.iter().enumerate().rev() {
+        let after = |offset: usize| depth.get(n + 1 + offset).copied().unwrap_or(0);
+        let code = insn.code;
+        depth[n] = match class(code) {
+            BPF_RET => 0,
+            BPF_JMP if code == BPF_JMP | BPF_JA => after(insn.k as usize),
+            BPF_JMP => 1 + after(insn.jt as usize).max(after(insn.jf as usize)),
+            _ => after(0),
+        };
+
+        match class(code) {
+            BPF_JMP => stats.jumps += 1,
+            BPF_RET if code == BPF_RET | BPF_A => stats.truncates = true,
+            BPF_RET if insn.k != 0 && insn.k < 65535 => stats.truncates = true,
+            BPF_ST | BPF_STX => stats.memory_accesses += 1,
+            BPF_LD | BPF_LDX => match mode(code) {
+                BPF_ABS | BPF_IND | BPF_MSH => stats.packet_loads += 1,
+                BPF_MEM => stats.memory_accesses += 1,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    stats.max_branch_depth = depth.first().copied().unwrap_or(0);
+
+    stats
+}
+
+// returns the fewest bytes of the packet the program's absolute loads reach on any path to a
+// `ret` that can accept it, or None if no `ret` can; like `max_access_offset()`, this doesn't
+// take indexed loads into account
+pub(crate) fn accept_reach(insns: &[bpf_insn_t]) -> Option<u32> {
+    // as in stats(), worked out from the end of the program backwards
+    let mut reach: Vec<Option<u32>> = vec![None; insns.len() + 1];
+    for (n, insn) in insns.iter().enumerate().rev() {
+        let after = |offset: usize| reach.get(n + 1 + offset).copied().flatten();
+        let code = insn.code;
+        let width = match code {
+            c if c == BPF_LD | BPF_W | BPF_ABS => Some(4),
+            c if c == BPF_LD | BPF_H | BPF_ABS => Some(2),
+            c if c == BPF_LD | BPF_B | BPF_ABS => Some(1),
+            c if c == BPF_LDX | BPF_B | BPF_MSH => Some(1),
+            _ => None,
+        };
+        reach[n] = match class(code) {
+            BPF_RET if code == BPF_RET | BPF_K && insn.k == 0 => None,
+            BPF_RET => Some(0),
+            BPF_JMP if code == BPF_JMP | BPF_JA => after(insn.k as usize),
+            BPF_JMP => match (after(insn.jt as usize), after(insn.jf as usize)) {
+                (Some(taken), Some(not_taken)) => Some(taken.min(not_taken)),
+                (taken, not_taken) => taken.or(not_taken),
+            },
+            _ => match width {
+                Some(width) => after(0).map(|rest| rest.max(insn.k.saturating_add(width))),
+                None => after(0),
+            },
+        };
+    }
+    reach.first().copied().flatten()
+}
@@ -0,0 +1,48 @@
+// tests/no_pcap.rs
+
+// run with `cargo test --no-default-features --test no_pcap`
+#![cfg(not(feature = "pcap"))]
+#![allow(clippy::bool_assert_comparison)]
+
+#[cfg(test)]
+mod tests {
+    use bpfjit_sys::*;
+
+    static UDP_123_PACKET: &[u8] = &[
+        0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x08, 0x00, 0x45,
+        0x00, 0x00, 0x4c, 0x00, 0x00, 0x40, 0x00, 0x35, 0x11, 0x03, 0x44, 0x12, 0x34, 0x56, 0x78,
+        0x9a, 0xbc, 0xde, 0xf0, 0xc9, 0x21, 0x00, 0x7b, 0x00, 0x38, 0xa2, 0xa2, 0x1b, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xdd, 0x32, 0x01, 0xe6, 0x58, 0xd4, 0xfd, 0xf3,
+    ];
+
+    #[test]
+    fn test_from_insns() -> Result<(), BpfJitError> {
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x28, jt: 0, jf: 0, k: 12 },    // ldh [12]
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 0x800 }, // jeq #0x800 jt 2 jf 3
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 65535 }, // ret #65535
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0 },     // ret #0
+        ];
+        let filter = BpfJit::from_insns(&insns)?;
+        assert_eq!(filter.is_jit(), true);
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(&UDP_123_PACKET[14..]), false);
+        let cloned_filter = filter.clone();
+        assert_eq!(cloned_filter.is_jit(), true);
+        assert_eq!(cloned_filter.matches(UDP_123_PACKET), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_program() {
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x15, jt: 5, jf: 0, k: 0 }, // jeq #0 jt 6 jf 1
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0 }, // ret #0
+        ];
+        assert!(BpfJit::from_insns(&insns).is_err());
+    }
+}
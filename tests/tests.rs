@@ -1,11 +1,18 @@
 // tests/tests.rs
 
+#![cfg(feature = "pcap")]
+#![allow(clippy::bool_assert_comparison)]
+
 #[cfg(test)]
 mod tests {
     use bpfjit_sys::*;
-    use std::error::Error;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+    use std::hash::{Hash, Hasher};
+    use std::thread;
 
-    static UDP_123_PACKET: &'static [u8] = &[
+    static UDP_123_PACKET: &[u8] = &[
         0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x08, 0x00, 0x45,
         0x00, 0x00, 0x4c, 0x00, 0x00, 0x40, 0x00, 0x35, 0x11, 0x03, 0x44, 0x12, 0x34, 0x56, 0x78,
         0x9a, 0xbc, 0xde, 0xf0, 0xc9, 0x21, 0x00, 0x7b, 0x00, 0x38, 0xa2, 0xa2, 0x1b, 0x00, 0x00,
@@ -14,7 +21,7 @@ mod tests {
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xdd, 0x32, 0x01, 0xe6, 0x58, 0xd4, 0xfd, 0xf3,
     ];
 
-    static TCP_NULL_PACKET: &'static [u8] = &[
+    static TCP_NULL_PACKET: &[u8] = &[
         0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0x08, 0x00, 0x45,
         0x28, 0x00, 0x28, 0xf9, 0x41, 0x00, 0x00, 0xf4, 0x06, 0x74, 0x02, 0x12, 0x34, 0x56, 0x78,
         0x9a, 0xbc, 0xde, 0xf0, 0xb5, 0x58, 0x02, 0x97, 0xcb, 0x86, 0x00, 0x00, 0x01, 0x00, 0x00,
@@ -22,7 +29,7 @@ mod tests {
     ];
 
     #[test]
-    fn test_udp_123_packet() -> Result<(), Box<Error>> {
+    fn test_udp_123_packet() -> Result<(), BpfJitError> {
         let l2_filter = BpfJit::new("udp dst port 123")?;
         assert_eq!(l2_filter.matches(UDP_123_PACKET), true);
         assert_eq!(l2_filter.matches(TCP_NULL_PACKET), false);
@@ -33,7 +40,7 @@ mod tests {
     }
 
     #[test]
-    fn test_tcp_null_packet() -> Result<(), Box<Error>> {
+    fn test_tcp_null_packet() -> Result<(), BpfJitError> {
         let l2_filter = BpfJit::new_ethernet("tcp src port 46424")?;
         assert_eq!(l2_filter.matches(TCP_NULL_PACKET), true);
         assert_eq!(l2_filter.matches(UDP_123_PACKET), false);
@@ -44,7 +51,7 @@ mod tests {
     }
 
     #[test]
-    fn test_clone() -> Result<(), Box<Error>> {
+    fn test_clone() -> Result<(), BpfJitError> {
         let filter = BpfJit::new("udp dst port 123")?;
         assert_eq!(filter.matches(UDP_123_PACKET), true);
         assert_eq!(filter.matches(TCP_NULL_PACKET), false);
@@ -53,4 +60,1922 @@ mod tests {
         assert_eq!(cloned_filter.matches(TCP_NULL_PACKET), false);
         Ok(())
     }
+
+    #[test]
+    fn test_error_kinds() {
+        match BpfJit::new("udp dst port") {
+            Err(BpfJitError::Compile { .. }) => (),
+            other => panic!("expected a compile error, got {:?}", other.err()),
+        }
+        match BpfJit::new("udp\0") {
+            Err(BpfJitError::NulByte(_)) => (),
+            other => panic!("expected a NUL byte error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_nul_byte() {
+        let err = BpfJit::new("tcp\0udp").unwrap_err();
+        match &err {
+            BpfJitError::NulByte(nul) => assert_eq!(nul.nul_position(), 3),
+            other => panic!("expected a NUL byte error, got {:?}", other),
+        }
+        assert_eq!(
+            err.to_string(),
+            "invalid cBPF expression: NUL byte at offset 3"
+        );
+    }
+
+    #[test]
+    fn test_long_filter() -> Result<(), BpfJitError> {
+        let mut filter = String::from(
+            "udp src port 51489 and udp dst port 123 and src host 18.52.86.120 and dst host 154.188.222.240",
+        );
+        for port in 1000..1064 {
+            filter.push_str(&format!(" and not port {}", port));
+        }
+        let filter = BpfJit::new(&filter)?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_error_message() {
+        match BpfJit::new("tcp port notanumber") {
+            Err(BpfJitError::Compile { message, offset }) => {
+                assert!(!message.is_empty());
+                assert!(message.chars().all(|c| !c.is_control()));
+                assert!(message.contains("'notanumber'"), "{}", message);
+                assert_eq!(offset, Some(9));
+            }
+            other => panic!("expected a compile error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_create_and_drop_many() -> Result<(), BpfJitError> {
+        for _ in 0..10000 {
+            let filter = BpfJit::new("udp dst port 123")?;
+            let cloned_filter = filter.clone();
+            drop(filter);
+            drop(cloned_filter);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_clone_outlives_original() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let cloned_filter = filter.clone();
+        drop(filter);
+        assert_eq!(cloned_filter.matches(UDP_123_PACKET), true);
+        assert_eq!(cloned_filter.matches(TCP_NULL_PACKET), false);
+        let recloned_filter = cloned_filter.clone();
+        drop(cloned_filter);
+        assert_eq!(recloned_filter.matches(UDP_123_PACKET), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_linktype() -> Result<(), BpfJitError> {
+        let l2_filter = BpfJit::new_with_linktype("ip", LinkType::Ethernet)?;
+        assert_eq!(l2_filter.matches(UDP_123_PACKET), true);
+        // DLT_LINUX_SLL puts the protocol field at a different offset than Ethernet does
+        let sll_filter = BpfJit::new_with_linktype("ip", LinkType::LinuxSll)?;
+        assert_eq!(sll_filter.matches(UDP_123_PACKET), false);
+        assert_ne!(l2_filter.instructions(), sll_filter.instructions());
+        Ok(())
+    }
+
+    #[test]
+    fn test_linktype_dlt() {
+        assert_eq!(LinkType::Ethernet.as_dlt(), 1);
+        assert_eq!(LinkType::LinuxSll.as_dlt(), 113);
+        for dlt in 0..1024 {
+            if let Some(linktype) = LinkType::from_dlt(dlt) {
+                assert_eq!(linktype.as_dlt(), dlt);
+            }
+        }
+        assert_eq!(LinkType::from_dlt(-1), None);
+        assert_eq!(LinkType::from_dlt(228), None);
+        assert_eq!(LinkType::Other(228).as_dlt(), 228);
+    }
+
+    #[test]
+    fn test_snaplen() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new_with_snaplen("ether proto 0x0800", LinkType::Ethernet, 14)?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_netmask() -> Result<(), BpfJitError> {
+        match BpfJit::new_with_netmask("ip broadcast", LinkType::Ethernet, PCAP_NETMASK_UNKNOWN) {
+            Err(BpfJitError::Compile { .. }) => (),
+            other => panic!("expected a compile error, got {:?}", other.err()),
+        }
+        let filter = BpfJit::new_with_netmask("ip broadcast", LinkType::Ethernet, 0xffffff00)?;
+        assert_eq!(filter.matches(UDP_123_PACKET), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unoptimized() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new_unoptimized("udp dst port 123", LinkType::Ethernet)?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        let optimized_filter = BpfJit::new("udp dst port 123")?;
+        assert_ne!(
+            filter.instructions().len(),
+            optimized_filter.instructions().len()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder() -> Result<(), BpfJitError> {
+        let filter = BpfJitBuilder::new().compile("udp dst port 123")?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        let filter = BpfJitBuilder::new()
+            .linktype(LinkType::RawIp)
+            .snaplen(128)
+            .netmask(0xffffff00)
+            .optimize(false)
+            .compile("udp dst port 123 and not ip broadcast")?;
+        assert_eq!(filter.matches(&UDP_123_PACKET[14..]), true);
+        assert_eq!(filter.matches(&TCP_NULL_PACKET[14..]), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_cc() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("ip")?;
+        assert_eq!(
+            filter.to_cc(),
+            "{ 0x28, 0, 0, 0x0000000c },\n\
+             { 0x15, 0, 1, 0x00000800 },\n\
+             { 0x6, 0, 0, 0x0000ffff },\n\
+             { 0x6, 0, 0, 0x00000000 },\n"
+        );
+        assert_eq!(filter.to_cc().lines().count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disassemble() -> Result<(), BpfJitError> {
+        assert_eq!(
+            BpfJit::new("ip")?.disassemble(),
+            "(000) ldh      [12]\n\
+             (001) jeq      #0x800           jt 2\tjf 3\n\
+             (002) ret      #65535\n\
+             (003) ret      #0\n"
+        );
+        assert_eq!(
+            BpfJit::new("ip[9] == 6")?.disassemble(),
+            "(000) ldh      [12]\n\
+             (001) jeq      #0x800           jt 2\tjf 5\n\
+             (002) ldb      [23]\n\
+             (003) jeq      #0x6             jt 4\tjf 5\n\
+             (004) ret      #65535\n\
+             (005) ret      #0\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_truncated() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("greater 100")?;
+        assert_eq!(filter.matches(UDP_123_PACKET), false);
+        assert_eq!(filter.matches_truncated(UDP_123_PACKET, 1500), true);
+        assert_eq!(
+            filter.matches_truncated(UDP_123_PACKET, UDP_123_PACKET.len()),
+            false
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_len() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new_with_snaplen("udp", LinkType::Ethernet, 42)?;
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 42);
+        assert_eq!(filter.filter_len(TCP_NULL_PACKET), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_raw() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        for packet in &[UDP_123_PACKET.to_vec(), TCP_NULL_PACKET.to_vec()] {
+            let matched =
+                unsafe { filter.matches_raw(packet.as_ptr(), packet.len(), packet.len()) };
+            assert_eq!(matched, filter.matches(packet));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_insns() -> Result<(), BpfJitError> {
+        let accept_all = [bpf_insn_t {
+            code: 0x06, // ret #65535
+            jt: 0,
+            jf: 0,
+            k: 65535,
+        }];
+        let filter = BpfJit::from_insns(&accept_all)?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), true);
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 65535);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_cc() -> Result<(), BpfJitError> {
+        let filter = BpfJit::from_cc(
+            "{\n\
+             { 0x28, 0, 0, 0x0000000c },\n\
+             {0x15,0,1,0x00000800},\n\
+             \t{ 0x6, 0, 0, 65535 }\n\
+             { 0x6, 0, 0, 0x00000000 },\n\
+             };\n",
+        )?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.to_cc(), BpfJit::from_cc(&filter.to_cc())?.to_cc());
+        match BpfJit::from_cc("{ 0x6, 0, 0, 0 },\n{ 0x6, 0, 0 },\n") {
+            Err(BpfJitError::Parse { line: 2, .. }) => (),
+            other => panic!("expected a parse error, got {:?}", other.err()),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_cc_round_trip() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let parsed_filter = BpfJit::from_cc(&filter.to_cc())?;
+        assert_eq!(parsed_filter.disassemble(), filter.disassemble());
+        assert_eq!(parsed_filter.matches(UDP_123_PACKET), true);
+        assert_eq!(parsed_filter.matches(TCP_NULL_PACKET), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_instructions() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("ip")?;
+        assert_eq!(filter.instructions().len(), 4);
+        assert_eq!(
+            filter.instructions()[0],
+            bpf_insn_t {
+                code: 0x28, // ldh [12]
+                jt: 0,
+                jf: 0,
+                k: 12,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_interpreter() -> Result<(), BpfJitError> {
+        let jit_filter = BpfJit::new("udp dst port 123")?;
+        assert_eq!(jit_filter.is_jit(), true);
+        let filter = BpfJitBuilder::new()
+            .jit(false)
+            .compile("udp dst port 123")?;
+        assert_eq!(filter.is_jit(), false);
+        for packet in &[UDP_123_PACKET, TCP_NULL_PACKET] {
+            assert_eq!(filter.filter_len(packet), jit_filter.filter_len(packet));
+        }
+        let cloned_filter = filter.clone();
+        assert_eq!(cloned_filter.is_jit(), false);
+        assert_eq!(cloned_filter.matches(UDP_123_PACKET), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_program() {
+        let insns = [
+            bpf_insn_t {
+                code: 0x28, // ldh [12]
+                jt: 0,
+                jf: 0,
+                k: 12,
+            },
+            bpf_insn_t {
+                code: 0x15, // jeq #0x800 jt 7 jf 2
+                jt: 5,
+                jf: 0,
+                k: 0x800,
+            },
+            bpf_insn_t {
+                code: 0x06, // ret #0
+                jt: 0,
+                jf: 0,
+                k: 0,
+            },
+        ];
+        match BpfJit::from_insns(&insns) {
+            Err(BpfJitError::InvalidProgram) => (),
+            other => panic!("expected an invalid program error, got {:?}", other.err()),
+        }
+        match BpfJit::from_insns(&insns[..1]) {
+            Err(BpfJitError::InvalidProgram) => (),
+            other => panic!("expected an invalid program error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_matches_many() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let packets: Vec<&[u8]> = vec![UDP_123_PACKET, TCP_NULL_PACKET, UDP_123_PACKET, &[]];
+        let expected: Vec<bool> = packets
+            .iter()
+            .map(|packet| filter.matches(packet))
+            .collect();
+        assert_eq!(filter.matches_many(packets), expected);
+        assert_eq!(expected, vec![true, false, true, false]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_count_matches() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let packets = [
+            UDP_123_PACKET,
+            TCP_NULL_PACKET,
+            UDP_123_PACKET,
+            TCP_NULL_PACKET,
+        ];
+        assert_eq!(filter.count_matches(packets.iter().cloned()), 2);
+        assert_eq!(filter.count_matches(vec![TCP_NULL_PACKET; 10]), 0);
+        assert_eq!(filter.count_matches(vec![UDP_123_PACKET; 10]), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_source() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("tcp port 80")?;
+        assert_eq!(filter.source(), "tcp port 80");
+        assert_eq!(filter.clone().source(), "tcp port 80");
+        assert_eq!(BpfJit::from_cc(&filter.to_cc())?.source(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("ip")?;
+        let formatted = format!("{:?}", filter);
+        assert!(formatted.contains("\"ip\""));
+        assert!(formatted.contains("instructions: 4"));
+        assert!(formatted.contains("Ethernet"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_display() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("ip")?;
+        let formatted = filter.to_string();
+        assert!(formatted.starts_with("ip\n"));
+        assert!(formatted.contains("ldh      [12]"));
+        assert!(formatted.ends_with(&filter.disassemble()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_instruction_count() -> Result<(), BpfJitError> {
+        let simple_filter = BpfJit::new("ip")?;
+        assert_eq!(simple_filter.instruction_count(), 4);
+        let complex_filter = BpfJit::new("tcp or udp or icmp or arp")?;
+        assert!(simple_filter.instruction_count() < complex_filter.instruction_count());
+        assert_eq!(
+            complex_filter.instruction_count(),
+            complex_filter.instructions().len()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_compile() {
+        let threads: Vec<_> = (0..32)
+            .map(|n| {
+                thread::spawn(move || {
+                    let filter = BpfJit::new(&format!("udp dst port {}", 100 + n)).unwrap();
+                    assert_eq!(filter.matches(UDP_123_PACKET), n == 23);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_shared() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?.shared();
+        let threads: Vec<_> = (0..32)
+            .map(|_| {
+                let filter = filter.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        assert_eq!(filter.matches(UDP_123_PACKET), true);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(std::sync::Arc::strong_count(&filter), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_matches() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?.shared();
+        let interpreted = BpfJitBuilder::new()
+            .jit(false)
+            .compile("udp dst port 123")?
+            .shared();
+        // stashes the packet length in external memory before returning it, so calls that
+        // shared their memory would see each other's lengths
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x80, jt: 0, jf: 0, k: 0 }, // ld #len
+            bpf_insn_t { code: 0x02, jt: 0, jf: 0, k: 3 }, // st M[3]
+            bpf_insn_t { code: 0x00, jt: 0, jf: 0, k: 0 }, // ld #0
+            bpf_insn_t { code: 0x60, jt: 0, jf: 0, k: 3 }, // ld M[3]
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 }, // ret a
+        ];
+        let echo = BpfJit::from_insns_with_context(&insns, BpfContext::new().extwords(4))?.shared();
+
+        let threads: Vec<_> = (0..32)
+            .map(|n| {
+                let (filter, interpreted, echo) =
+                    (filter.clone(), interpreted.clone(), echo.clone());
+                thread::spawn(move || {
+                    let (packet, expected) = if n % 2 == 0 {
+                        (UDP_123_PACKET, true)
+                    } else {
+                        (TCP_NULL_PACKET, false)
+                    };
+                    for _ in 0..1000 {
+                        assert_eq!(filter.matches(packet), expected);
+                        assert_eq!(interpreted.matches(packet), expected);
+                        assert_eq!(echo.filter_len(&packet[n..]), (packet.len() - n) as u32);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_libpcap_version() {
+        assert!(!libpcap_version().is_empty());
+        assert!(libpcap_version().contains("libpcap"));
+    }
+
+    #[test]
+    fn test_construct_and_drop() -> Result<(), BpfJitError> {
+        let accept_all = [bpf_insn_t {
+            code: 0x06, // ret #65535
+            jt: 0,
+            jf: 0,
+            k: 65535,
+        }];
+        for _ in 0..1000 {
+            drop(BpfJit::new("tcp port 80")?);
+            drop(BpfJitBuilder::new().jit(false).compile("tcp port 80")?);
+            drop(BpfJit::from_insns(&accept_all)?);
+            drop(BpfJit::new("tcp port notanumber").err());
+            drop(BpfJit::from_insns(&[]).err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_memory() -> Result<(), BpfJitError> {
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x00, jt: 0, jf: 0, k: 42 }, // ld #42
+            bpf_insn_t { code: 0x02, jt: 0, jf: 0, k: 12 }, // st M[12]
+            bpf_insn_t { code: 0x00, jt: 0, jf: 0, k: 0 },  // ld #0
+            bpf_insn_t { code: 0x60, jt: 0, jf: 0, k: 12 }, // ld M[12]
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 42 }, // jeq #42 jt 5 jf 6
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 1 },  // ret #1
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0 },  // ret #0
+        ];
+        let filter = BpfJit::from_insns_with_context(&insns, BpfContext::new().extwords(16))?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.clone().matches(TCP_NULL_PACKET), true);
+        // M[12] is out of range for a 12-word external memory store
+        assert!(BpfJit::from_insns_with_context(&insns, BpfContext::new().extwords(12)).is_err());
+        Ok(())
+    }
+
+    extern "C" fn answer(_ctx: *const bpf_ctx_t, _args: *mut bpf_args_t, _a: u32) -> u32 {
+        42
+    }
+
+    extern "C" fn double(_ctx: *const bpf_ctx_t, _args: *mut bpf_args_t, a: u32) -> u32 {
+        a * 2
+    }
+
+    #[test]
+    fn test_copfuncs() -> Result<(), BpfJitError> {
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x27, jt: 0, jf: 0, k: 0 },  // cop #0
+            bpf_insn_t { code: 0x01, jt: 0, jf: 0, k: 1 },  // ldx #1
+            bpf_insn_t { code: 0x47, jt: 0, jf: 0, k: 0 },  // copx
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 84 }, // jeq #84 jt 4 jf 5
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 1 },  // ret #1
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0 },  // ret #0
+        ];
+        let ctx = BpfContext::new().copfuncs(&[answer, double]);
+        let filter = BpfJit::from_insns_with_context(&insns, ctx.clone())?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        drop(ctx);
+        assert_eq!(filter.clone().matches(TCP_NULL_PACKET), true);
+        // cop #0 needs at least one coprocessor function
+        assert!(BpfJit::from_insns_with_context(&insns, BpfContext::new()).is_err());
+        Ok(())
+    }
+
+    extern "C" fn read_arg(_ctx: *const bpf_ctx_t, args: *mut bpf_args_t, _a: u32) -> u32 {
+        unsafe { *((*args).arg as *const u32) }
+    }
+
+    #[test]
+    fn test_matches_with_arg() -> Result<(), BpfJitError> {
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x27, jt: 0, jf: 0, k: 0 }, // cop #0
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 }, // ret a
+        ];
+        let ctx = BpfContext::new().copfuncs(&[read_arg]);
+        let filter = BpfJit::from_insns_with_context(&insns, ctx)?;
+        let mut arg = 1u32;
+        assert_eq!(
+            filter.matches_with_arg(UDP_123_PACKET, &mut arg as *mut u32 as *mut _),
+            true
+        );
+        arg = 0;
+        assert_eq!(
+            filter.matches_with_arg(UDP_123_PACKET, &mut arg as *mut u32 as *mut _),
+            false
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scratch_memory() -> Result<(), BpfJitError> {
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x00, jt: 0, jf: 0, k: 7 }, // ld #7
+            bpf_insn_t { code: 0x02, jt: 0, jf: 0, k: 3 }, // st M[3]
+            bpf_insn_t { code: 0x00, jt: 0, jf: 0, k: 0 }, // ld #0
+            bpf_insn_t { code: 0x60, jt: 0, jf: 0, k: 3 }, // ld M[3]
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 }, // ret a
+        ];
+        let filter = BpfJit::from_insns(&insns)?;
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 7);
+        let filter = BpfJit::from_insns_with_context(&insns, BpfContext::new().extwords(4))?;
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 7);
+
+        // increments M[3] on every call, so this only returns 1 if it starts out zeroed
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x60, jt: 0, jf: 0, k: 3 }, // ld M[3]
+            bpf_insn_t { code: 0x04, jt: 0, jf: 0, k: 1 }, // add #1
+            bpf_insn_t { code: 0x02, jt: 0, jf: 0, k: 3 }, // st M[3]
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 }, // ret a
+        ];
+        let filter = BpfJit::from_insns_with_context(&insns, BpfContext::new().extwords(4))?;
+        for _ in 0..3 {
+            assert_eq!(filter.filter_len(UDP_123_PACKET), 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_from() -> Result<(), BpfJitError> {
+        #[rustfmt::skip]
+        let insns = vec![
+            bpf_insn_t { code: 0x28, jt: 0, jf: 0, k: 12 },     // ldh [12]
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 0x800 },  // jeq #0x800 jt 2 jf 3
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 65535 },  // ret #65535
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0 },      // ret #0
+        ];
+        let filter = BpfJit::try_from(&insns[..])?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(&UDP_123_PACKET[14..]), false);
+        let filter = BpfJit::try_from(insns)?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(&UDP_123_PACKET[14..]), false);
+        assert!(BpfJit::try_from(Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_bytes() -> Result<(), BpfJitError> {
+        let filter = BpfJit::from_cc(
+            "{ 0x28, 0, 0, 0x0000000c },\n\
+             { 0x15, 0, 1, 0x00000800 },\n\
+             { 0x6, 0, 0, 0x0000ffff },\n\
+             { 0x6, 0, 0, 0x00000000 },\n",
+        )?;
+        let bytes = filter.to_bytes();
+        assert_eq!(&bytes[..9], b"BPFJ\x01\x04\x00\x00\x00");
+        assert_eq!(
+            &bytes[9..17],
+            &[0x28, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00]
+        );
+        let loaded = BpfJit::from_bytes(&bytes)?;
+        assert_eq!(loaded.instructions(), filter.instructions());
+        assert_eq!(loaded.matches(UDP_123_PACKET), true);
+        assert_eq!(loaded.matches(&UDP_123_PACKET[14..]), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_bytes_invalid() -> Result<(), BpfJitError> {
+        let bytes = BpfJit::from_cc("{ 0x6, 0, 0, 0x0000ffff },\n")?.to_bytes();
+        for len in 0..bytes.len() {
+            match BpfJit::from_bytes(&bytes[..len]) {
+                Err(BpfJitError::InvalidFormat) => (),
+                result => panic!("unexpected result for {} bytes: {:?}", len, result),
+            }
+        }
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(BpfJit::from_bytes(&extra).is_err());
+        let mut version = bytes;
+        version[4] = 2;
+        assert!(BpfJit::from_bytes(&version).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_sock_filter() -> Result<(), BpfJitError> {
+        let filter = BpfJit::from_cc(
+            "{ 0x28, 0, 0, 0x0000000c },\n\
+             { 0x15, 0, 1, 0x00000800 },\n\
+             { 0x6, 0, 0, 0x0000ffff },\n\
+             { 0x6, 0, 0, 0x00000000 },\n",
+        )?;
+        let sock_filter = filter.to_sock_filter();
+        assert_eq!(sock_filter.len(), filter.instruction_count());
+        for (insn, sock_insn) in filter.instructions().iter().zip(&sock_filter) {
+            assert_eq!(sock_insn.code, insn.code);
+            assert_eq!(sock_insn.jt, insn.jt);
+            assert_eq!(sock_insn.jf, insn.jf);
+            assert_eq!(sock_insn.k, insn.k);
+        }
+        assert_eq!(std::mem::size_of::<SockFilter>(), 8);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_attach_to_fd() -> Result<(), BpfJitError> {
+        let filter = BpfJit::from_cc(
+            "{ 0x28, 0, 0, 0x0000000c },\n\
+             { 0x15, 0, 1, 0x00000800 },\n\
+             { 0x6, 0, 0, 0x0000ffff },\n\
+             { 0x6, 0, 0, 0x00000000 },\n",
+        )?;
+        assert!(filter.attach_to_fd(-1).is_err());
+        let protocol = (libc::ETH_P_ALL as u16).to_be() as libc::c_int;
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol) };
+        if fd < 0 {
+            // packet sockets need CAP_NET_RAW, so there's nothing more to test without it
+            return Ok(());
+        }
+        let result = filter.attach_to_fd(fd);
+        unsafe { libc::close(fd) };
+        result.unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_eq() -> Result<(), BpfJitError> {
+        assert_eq!(BpfJit::new("tcp")?, BpfJit::new("tcp")?);
+        assert_ne!(BpfJit::new("tcp")?, BpfJit::new("udp")?);
+        let filter = BpfJit::new("udp dst port 123")?;
+        assert_eq!(filter.clone(), filter);
+        assert_eq!(BpfJit::from_insns(filter.instructions())?, filter);
+        Ok(())
+    }
+
+    fn hash_of(filter: &BpfJit) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        filter.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_hash() -> Result<(), BpfJitError> {
+        let ip = BpfJit::from_cc("{ 0x28, 0, 0, 0x0000000c },\n{ 0x15, 0, 1, 0x00000800 },\n{ 0x6, 0, 0, 0x0000ffff },\n{ 0x6, 0, 0, 0x00000000 },\n")?;
+        let accept_all = BpfJit::from_cc("{ 0x6, 0, 0, 0x0000ffff },\n")?;
+        assert_eq!(hash_of(&ip), hash_of(&ip.clone()));
+        assert_ne!(hash_of(&ip), hash_of(&accept_all));
+        let set: HashSet<BpfJit> = vec![ip.clone(), accept_all, ip].into_iter().collect();
+        assert_eq!(set.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_cached() -> Result<(), BpfJitError> {
+        let options = BpfJitBuilder::new().snaplen(1234);
+        let hits = BpfJit::cache_hits();
+        let first = BpfJit::new_cached("udp dst port 123", &options)?;
+        let second = BpfJit::new_cached("udp dst port 123", &options)?;
+        assert!(BpfJit::cache_hits() > hits);
+        assert_eq!(first, second);
+        assert_eq!(second.matches(UDP_123_PACKET), true);
+        assert_eq!(second.filter_len(UDP_123_PACKET), 1234);
+        assert!(BpfJit::new_cached("udp dst port", &options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_and() -> Result<(), BpfJitError> {
+        let mut ipv6_packet = TCP_NULL_PACKET.to_vec();
+        ipv6_packet[12..14].copy_from_slice(&[0x86, 0xdd]);
+        let filter = BpfJit::new("ip")?.and(&BpfJit::new("tcp")?)?;
+        assert_eq!(filter.matches(TCP_NULL_PACKET), true);
+        assert_eq!(filter.matches(UDP_123_PACKET), false);
+        assert_eq!(filter.matches(&ipv6_packet), false);
+        assert_eq!(filter.source(), "(ip) and (tcp)");
+
+        // `ret a` accepts whatever is in the accumulator, which is the IP version and header
+        // length here, and the second program must still start out with A, X and M[] zeroed
+        #[rustfmt::skip]
+        let first = BpfJit::from_insns(&[
+            bpf_insn_t { code: 0x30, jt: 0, jf: 0, k: 14 }, // ldb [14]
+            bpf_insn_t { code: 0x02, jt: 0, jf: 0, k: 1 },  // st M[1]
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 },  // ret a
+        ])?;
+        #[rustfmt::skip]
+        let second = BpfJit::from_insns(&[
+            bpf_insn_t { code: 0x60, jt: 0, jf: 0, k: 1 }, // ld M[1]
+            bpf_insn_t { code: 0x0c, jt: 0, jf: 0, k: 0 }, // add x
+            bpf_insn_t { code: 0x04, jt: 0, jf: 0, k: 7 }, // add #7
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 }, // ret a
+        ])?;
+        let filter = first.and(&second)?;
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 7);
+        assert_eq!(filter.filter_len(&UDP_123_PACKET[..14]), 0);
+        assert_eq!(filter.source(), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_or() -> Result<(), BpfJitError> {
+        let mut icmp_packet = UDP_123_PACKET.to_vec();
+        icmp_packet[23] = 1;
+        let filter = BpfJit::new("tcp")?.or(&BpfJit::new("udp")?)?;
+        assert_eq!(filter.matches(TCP_NULL_PACKET), true);
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(&icmp_packet), false);
+        assert_eq!(filter.source(), "(tcp) or (udp)");
+
+        let short = BpfJit::new_with_snaplen("tcp", LinkType::Ethernet, 100)?;
+        let long = BpfJit::new_with_snaplen("udp", LinkType::Ethernet, 200)?;
+        let filter = short.or(&long)?;
+        assert_eq!(filter.filter_len(TCP_NULL_PACKET), 100);
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 200);
+        Ok(())
+    }
+
+    #[test]
+    fn test_negate() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("tcp")?.negate()?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 65535);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        assert_eq!(filter.source(), "not (tcp)");
+        assert_eq!(
+            filter.negate()?.instructions(),
+            BpfJit::new("tcp")?.instructions()
+        );
+
+        #[rustfmt::skip]
+        let filter = BpfJit::from_insns(&[
+            bpf_insn_t { code: 0x30, jt: 0, jf: 0, k: 15 }, // ldb [15]
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 },  // ret a
+        ])?
+        .negate()?;
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 65535);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        // loads past the end of the packet still reject it
+        assert_eq!(filter.matches(&UDP_123_PACKET[..14]), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_match_all() -> Result<(), BpfJitError> {
+        assert_eq!(BpfJit::new("")?.is_match_all(), true);
+        assert_eq!(BpfJit::new("tcp")?.is_match_all(), false);
+        assert_eq!(BpfJit::new("tcp")?.negate()?.is_match_all(), false);
+        assert_eq!(
+            BpfJit::from_cc("{ 0x6, 0, 0, 0x00000000 },\n")?.is_match_all(),
+            false
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "static")]
+    fn test_static_link() -> Result<(), BpfJitError> {
+        assert!(libpcap_version().starts_with("libpcap version"));
+        let filter = BpfJit::new("udp dst port 123")?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "vendored")]
+    fn test_vendored() -> Result<(), BpfJitError> {
+        // unless the build was pointed at other source, it is the pinned release
+        if option_env!("LIBPCAP_SRC").is_none() {
+            assert!(
+                libpcap_version().contains("1.10.4"),
+                "{}",
+                libpcap_version()
+            );
+        }
+        let filter = BpfJit::new("udp dst port 123")?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_linktype_from_name() -> Result<(), BpfJitError> {
+        assert_eq!(linktype_from_name("EN10MB"), Some(1));
+        assert_eq!(linktype_from_name("LINUX_SLL"), Some(113));
+        assert_eq!(linktype_from_name("NOT_A_LINKTYPE"), None);
+        let filter = BpfJit::new_with_linktype_name("udp dst port 123", "EN10MB")?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        // DLT_IPV4, which has no variant of its own
+        let filter = BpfJit::new_with_linktype_name("udp dst port 123", "IPV4")?;
+        assert_eq!(filter.linktype(), Some(LinkType::Other(228)));
+        assert_eq!(filter.matches(&UDP_123_PACKET[14..]), true);
+        match BpfJit::new_with_linktype_name("udp", "NOT_A_LINKTYPE") {
+            Err(BpfJitError::UnknownLinkType(name)) => assert_eq!(name, "NOT_A_LINKTYPE"),
+            other => panic!("expected an unknown link-layer type, got {:?}", other.err()),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_supported_linktypes() {
+        let linktypes = supported_linktypes();
+        assert!(linktypes.contains(&(1, String::from("EN10MB"))));
+        assert!(linktypes.iter().all(|(_, name)| !name.is_empty()));
+    }
+
+    #[test]
+    fn test_new_ipv6() -> Result<(), BpfJitError> {
+        let mut tcp_443_packet = vec![0u8; 60];
+        tcp_443_packet[0] = 0x60; // version 6
+        tcp_443_packet[5] = 20; // payload length
+        tcp_443_packet[6] = 6; // next header: TCP
+        tcp_443_packet[7] = 64; // hop limit
+        tcp_443_packet[40..44].copy_from_slice(&[0xc3, 0x50, 0x01, 0xbb]); // 50000 -> 443
+        tcp_443_packet[52] = 0x50; // data offset
+        let filter = BpfJit::new_ipv6("ip6 and tcp port 443")?;
+        assert_eq!(filter.matches(&tcp_443_packet), true);
+        tcp_443_packet[42..44].copy_from_slice(&[0x00, 0x50]); // 50000 -> 80
+        assert_eq!(filter.matches(&tcp_443_packet), false);
+        assert_eq!(filter.matches(&TCP_NULL_PACKET[14..]), false);
+        Ok(())
+    }
+
+    extern "C" {
+        fn pcap_open_dead(linktype: libc::c_int, snaplen: libc::c_int) -> *mut libc::c_void;
+        fn pcap_compile(
+            p: *mut libc::c_void,
+            fp: *mut bpf_program_t,
+            s: *const libc::c_char,
+            optimize: libc::c_int,
+            netmask: u32,
+        ) -> libc::c_int;
+        fn pcap_freecode(fp: *mut bpf_program_t);
+        fn pcap_close(p: *mut libc::c_void);
+    }
+
+    // compiles `filter` the way tcpdump does, with pcap_compile() on a dead handle
+    fn compile_with_handle(filter: &str, linktype: LinkType, optimize: bool) -> Vec<bpf_insn_t> {
+        let filter = std::ffi::CString::new(filter).unwrap();
+        unsafe {
+            let pcap = pcap_open_dead(linktype.as_dlt(), 65535);
+            assert!(!pcap.is_null());
+            let mut prog = bpf_program_t {
+                bf_len: 0,
+                bf_insns: std::ptr::null_mut(),
+            };
+            let compiled = pcap_compile(
+                pcap,
+                &mut prog,
+                filter.as_ptr(),
+                optimize as libc::c_int,
+                PCAP_NETMASK_UNKNOWN,
+            );
+            pcap_close(pcap);
+            assert_eq!(compiled, 0, "pcap_compile() failed for {:?}", filter);
+            let insns = std::slice::from_raw_parts(prog.bf_insns, prog.bf_len as usize).to_vec();
+            pcap_freecode(&mut prog);
+            insns
+        }
+    }
+
+    #[test]
+    fn test_compile_output() -> Result<(), BpfJitError> {
+        // pcap_compile_nopcap() must produce exactly what the handle-based path does
+        for &(filter, linktype) in &[
+            ("ip", LinkType::Ethernet),
+            ("udp dst port 123", LinkType::Ethernet),
+            ("tcp port 80", LinkType::Ethernet),
+            ("udp or tcp", LinkType::Ethernet),
+            ("udp", LinkType::RawIp),
+        ] {
+            for &optimize in &[true, false] {
+                let options = BpfJitBuilder::new().linktype(linktype).optimize(optimize);
+                assert_eq!(
+                    BpfJit::compile_only(filter, &options)?,
+                    compile_with_handle(filter, linktype, optimize),
+                    "{:?} for {:?}, optimize = {}",
+                    filter,
+                    linktype,
+                    optimize
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_iter() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let packets = [
+            UDP_123_PACKET,
+            TCP_NULL_PACKET,
+            UDP_123_PACKET,
+            TCP_NULL_PACKET,
+        ];
+        let matching: Vec<&[u8]> = filter.filter_iter(packets.iter().copied()).collect();
+        assert_eq!(matching, [UDP_123_PACKET, UDP_123_PACKET]);
+        assert_eq!(filter.filter_iter(vec![TCP_NULL_PACKET]).count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "pcap-interop")]
+    fn test_matches_packet() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/udp_tcp.pcap");
+        let mut capture = pcap::Capture::from_file(path).unwrap();
+        let mut count = 0;
+        while let Ok(packet) = capture.next_packet() {
+            if filter.matches_packet(&packet) {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 3);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(target_os = "linux", feature = "socket2-interop"))]
+    fn test_attach_to_socket() -> Result<(), BpfJitError> {
+        use socket2::{Domain, Socket, Type};
+
+        // unlike packet sockets, UDP sockets don't need any privileges
+        let filter = BpfJit::new_ip("udp dst port 123")?;
+        let sock = Socket::new(Domain::IPV4, Type::DGRAM, None).unwrap();
+        filter.attach_to_socket(&sock).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_error_verbatim() {
+        let err = BpfJit::new("udp dst port").unwrap_err();
+        let text = err.to_string();
+        match err {
+            BpfJitError::Compile { message, offset } => {
+                assert!(message.contains("syntax error"));
+                assert_eq!(
+                    text,
+                    format!("could not compile cBPF expression: {}", message)
+                );
+                assert_eq!(offset, None);
+            }
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_program() {
+        match BpfJit::from_insns(&[]) {
+            Err(BpfJitError::EmptyProgram) => (),
+            other => panic!("expected an empty program error, got {:?}", other.err()),
+        }
+        match BpfJit::from_insns_with_context(&[], BpfContext::new()) {
+            Err(BpfJitError::EmptyProgram) => (),
+            other => panic!("expected an empty program error, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn test_interpreter_matching() -> Result<(), BpfJitError> {
+        let filter = BpfJitBuilder::new()
+            .jit(false)
+            .compile("udp dst port 123")?;
+        assert_eq!(filter.is_jit(), false);
+        let packets = [UDP_123_PACKET, TCP_NULL_PACKET, &[]];
+        assert_eq!(
+            filter.matches_many(packets.iter().copied()),
+            [true, false, false]
+        );
+        assert_eq!(filter.count_matches(packets.iter().copied()), 1);
+        assert_eq!(filter.matches_truncated(&UDP_123_PACKET[..40], 90), true);
+        assert_eq!(
+            filter.matches_with_arg(UDP_123_PACKET, std::ptr::null_mut()),
+            true
+        );
+        assert_eq!(filter.filter_len(&[]), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_file() -> Result<(), BpfJitError> {
+        let path = std::env::temp_dir().join(format!("bpfjit-sys-{}.filter", std::process::id()));
+        std::fs::write(
+            &path,
+            "# NTP traffic only\nudp\n  and dst port 123\n\n# end\n",
+        )?;
+        let filter = BpfJit::from_file(&path);
+        std::fs::remove_file(&path)?;
+        let filter = filter?;
+        assert_eq!(filter.source(), "udp and dst port 123");
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+
+        match BpfJit::from_file(std::env::temp_dir().join("bpfjit-sys-missing.filter")) {
+            Err(BpfJitError::Io(_)) => {}
+            other => panic!("expected BpfJitError::Io, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_instructions_owned() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let insns = filter.instructions().to_vec();
+        let loaded = BpfJit::from_insns(&insns)?;
+        assert_eq!(loaded.instructions(), &insns[..]);
+
+        let cloned = loaded.clone();
+        assert_eq!(cloned.instructions(), loaded.instructions());
+        assert_ne!(
+            cloned.instructions().as_ptr(),
+            loaded.instructions().as_ptr()
+        );
+        drop(loaded);
+        assert_eq!(cloned.instructions(), &insns[..]);
+        assert_eq!(cloned.matches(UDP_123_PACKET), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_at() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let mut buf = vec![0xFFu8; 7];
+        buf.extend_from_slice(UDP_123_PACKET);
+        buf.extend_from_slice(TCP_NULL_PACKET);
+        let tcp_offset = 7 + UDP_123_PACKET.len();
+        assert_eq!(filter.matches_at(&buf, 7, UDP_123_PACKET.len()), true);
+        assert_eq!(
+            filter.matches_at(&buf, tcp_offset, TCP_NULL_PACKET.len()),
+            false
+        );
+        assert_eq!(filter.matches_at(&buf, 0, UDP_123_PACKET.len()), false);
+        assert_eq!(filter.matches_at(&buf, buf.len(), 0), false);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the")]
+    fn test_matches_at_out_of_bounds() {
+        let filter = BpfJit::new("udp dst port 123").unwrap();
+        filter.matches_at(UDP_123_PACKET, 1, UDP_123_PACKET.len());
+    }
+
+    #[test]
+    fn test_accept_all_reject_all() -> Result<(), BpfJitError> {
+        let accept = BpfJit::accept_all(LinkType::Ethernet)?;
+        let reject = BpfJit::reject_all(LinkType::Ethernet)?;
+        assert_eq!(accept.is_match_all(), true);
+        assert_eq!(reject.is_match_all(), false);
+        for packet in &[UDP_123_PACKET, TCP_NULL_PACKET, &[][..]] {
+            assert_eq!(accept.matches(packet), true);
+            assert_eq!(reject.matches(packet), false);
+        }
+        assert_eq!(accept.filter_len(UDP_123_PACKET), 65535);
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_access_offset() -> Result<(), BpfJitError> {
+        // ether[14] = 0x45 or ether[12:2] = 0x86dd
+        #[rustfmt::skip]
+        let filter = BpfJit::from_insns(&[
+            bpf_insn_t { code: 0x30, jt: 0, jf: 0, k: 0x0000000e },
+            bpf_insn_t { code: 0x15, jt: 2, jf: 0, k: 0x00000045 },
+            bpf_insn_t { code: 0x28, jt: 0, jf: 0, k: 0x0000000c },
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 0x000086dd },
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0x0000ffff },
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0x00000000 },
+        ])?;
+        assert_eq!(filter.max_access_offset(), Some(15));
+
+        // ldx 4*([14]&0xf); ld [x + 16]
+        #[rustfmt::skip]
+        let filter = BpfJit::from_insns(&[
+            bpf_insn_t { code: 0xb1, jt: 0, jf: 0, k: 0x0000000e },
+            bpf_insn_t { code: 0x40, jt: 0, jf: 0, k: 0x00000010 },
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0x00000000 },
+        ])?;
+        assert_eq!(filter.max_access_offset(), Some(15));
+
+        // ld [x + 16]
+        #[rustfmt::skip]
+        let filter = BpfJit::from_insns(&[
+            bpf_insn_t { code: 0x40, jt: 0, jf: 0, k: 0x00000010 },
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0x00000000 },
+        ])?;
+        assert_eq!(filter.max_access_offset(), None);
+        assert_eq!(
+            BpfJit::accept_all(LinkType::Ethernet)?.max_access_offset(),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_vec() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let insns = Vec::from(&filter);
+        assert_eq!(insns.len(), filter.instruction_count());
+        drop(filter);
+        let loaded = BpfJit::try_from(insns)?;
+        assert_eq!(loaded.matches(UDP_123_PACKET), true);
+        assert_eq!(loaded.matches(TCP_NULL_PACKET), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_with_bytecode() -> Result<(), BpfJitError> {
+        let options = BpfJitBuilder::new();
+        let (filter, insns) = BpfJit::compile_with_bytecode("udp dst port 123", &options)?;
+        assert_eq!(filter.instructions(), &insns[..]);
+        assert_eq!(filter.is_jit(), true);
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+        assert!(BpfJit::compile_with_bytecode("tcp port notanumber", &options).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matcher() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let mut matcher = filter.matcher();
+        for _ in 0..3 {
+            assert_eq!(matcher.matches(UDP_123_PACKET), true);
+            assert_eq!(matcher.run(TCP_NULL_PACKET), 0);
+        }
+        assert_eq!(
+            matcher.run(UDP_123_PACKET),
+            filter.filter_len(UDP_123_PACKET)
+        );
+
+        // increments M[3] on every call, so this only returns 1 if it starts out zeroed
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x60, jt: 0, jf: 0, k: 3 }, // ld M[3]
+            bpf_insn_t { code: 0x04, jt: 0, jf: 0, k: 1 }, // add #1
+            bpf_insn_t { code: 0x02, jt: 0, jf: 0, k: 3 }, // st M[3]
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 }, // ret a
+        ];
+        let filter = BpfJit::from_insns_with_context(&insns, BpfContext::new().extwords(4))?;
+        let mut matcher = filter.matcher();
+        for _ in 0..3 {
+            assert_eq!(matcher.run(UDP_123_PACKET), 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_radiotap() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new_radiotap("wlan type mgt")?;
+        assert_eq!(filter.source(), "wlan type mgt");
+        assert_ne!(filter.instruction_count(), 0);
+        // 802.11 filters don't compile for other link-layer types
+        assert!(BpfJit::new_ethernet("wlan type mgt").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_linux_sll() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new_linux_sll("tcp port 22")?;
+        assert_ne!(filter.instruction_count(), 0);
+        if linktype_from_name("LINUX_SLL2").is_some() {
+            let filter2 = BpfJit::new_linux_sll2("tcp port 22")?;
+            assert_ne!(filter2.instruction_count(), 0);
+        } else {
+            match BpfJit::new_linux_sll2("tcp port 22") {
+                Err(BpfJitError::UnknownLinkType(_)) => {}
+                other => panic!("expected BpfJitError::UnknownLinkType, got {:?}", other),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_loopback() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new_loopback("ip6")?;
+        assert_eq!(filter.source(), "ip6");
+        assert_ne!(filter.instruction_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_linktype_mismatch() -> Result<(), BpfJitError> {
+        let l2_filter = BpfJit::new_ethernet("udp dst port 123")?;
+        let sll_filter = BpfJit::new_linux_sll("tcp port 22")?;
+        assert_eq!(l2_filter.linktype(), Some(LinkType::Ethernet));
+        assert_eq!(sll_filter.linktype(), Some(LinkType::LinuxSll));
+        match l2_filter.and(&sll_filter) {
+            Err(BpfJitError::LinktypeMismatch(LinkType::Ethernet, LinkType::LinuxSll)) => {}
+            other => panic!("expected BpfJitError::LinktypeMismatch, got {:?}", other),
+        }
+        assert!(sll_filter.or(&l2_filter).is_err());
+
+        // programs loaded from raw instructions can be combined with anything
+        let raw_filter = BpfJit::from_insns(l2_filter.instructions())?;
+        assert_eq!(raw_filter.linktype(), None);
+        assert_eq!(raw_filter.and(&sll_filter)?.linktype(), None);
+        assert_eq!(
+            l2_filter.or(&l2_filter)?.linktype(),
+            Some(LinkType::Ethernet)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_packet() -> Result<(), BpfJitError> {
+        // every load is out of range, which rejects the packet rather than reading past it
+        let filter = BpfJit::new("tcp port 80")?;
+        let interpreted = BpfJitBuilder::new().jit(false).compile("tcp port 80")?;
+        for filter in &[filter, interpreted] {
+            assert_eq!(filter.matches(&[]), false);
+            assert_eq!(filter.matches_truncated(&[], 60), false);
+            assert_eq!(filter.matcher().matches(&[]), false);
+        }
+        Ok(())
+    }
+
+    // copies `insns` into a malloc()'d program, as libpcap would have compiled it
+    fn raw_program(insns: &[bpf_insn_t]) -> bpf_program_t {
+        unsafe {
+            let buf = libc::malloc(std::mem::size_of_val(insns)) as *mut bpf_insn_t;
+            assert!(!buf.is_null());
+            std::ptr::copy_nonoverlapping(insns.as_ptr(), buf, insns.len());
+            bpf_program_t {
+                bf_len: insns.len() as u32,
+                bf_insns: buf,
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_raw_program() -> Result<(), BpfJitError> {
+        let compiled = BpfJit::new("udp dst port 123")?;
+        let filter = unsafe { BpfJit::from_raw_program(raw_program(compiled.instructions()))? };
+        assert_eq!(filter, compiled);
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+
+        // rejected programs are freed too
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x05, jt: 0, jf: 0, k: 8 }, // ja +8
+        ];
+        match unsafe { BpfJit::from_raw_program(raw_program(&insns)) } {
+            Err(BpfJitError::InvalidProgram) => {}
+            other => panic!("expected BpfJitError::InvalidProgram, got {:?}", other),
+        }
+        let empty = bpf_program_t {
+            bf_len: 0,
+            bf_insns: std::ptr::null_mut(),
+        };
+        match unsafe { BpfJit::from_raw_program(empty) } {
+            Err(BpfJitError::EmptyProgram) => {}
+            other => panic!("expected BpfJitError::EmptyProgram, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_only() -> Result<(), BpfJitError> {
+        let insns = BpfJit::compile_only("udp", &BpfJitBuilder::new())?;
+        assert!(!insns.is_empty());
+        assert_eq!(&insns[..], BpfJit::new("udp")?.instructions());
+        assert!(BpfJit::compile_only("tcp port notanumber", &BpfJitBuilder::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_instructions() -> Result<(), BpfJitError> {
+        let filter = (1..=50)
+            .map(|port| format!("udp port {}", port))
+            .collect::<Vec<_>>()
+            .join(" or ");
+        let len = BpfJit::new(&filter)?.instruction_count();
+        match BpfJitBuilder::new().max_instructions(16).compile(&filter) {
+            Err(BpfJitError::ProgramTooLarge { len: actual, limit }) => {
+                assert_eq!(actual, len);
+                assert_eq!(limit, 16);
+            }
+            other => panic!("expected BpfJitError::ProgramTooLarge, got {:?}", other),
+        }
+        let compiled = BpfJitBuilder::new()
+            .max_instructions(len)
+            .compile(&filter)?;
+        assert_eq!(compiled.instruction_count(), len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_with_snaplen() -> Result<(), BpfJitError> {
+        // the destination port is at offset 36 of the Ethernet frame
+        let filter = BpfJit::new("udp dst port 123")?;
+        assert_eq!(filter.matches_with_snaplen(UDP_123_PACKET, 65535), true);
+        assert_eq!(filter.matches_with_snaplen(UDP_123_PACKET, 38), true);
+        assert_eq!(filter.matches_with_snaplen(UDP_123_PACKET, 37), false);
+        assert_eq!(filter.matches_with_snaplen(UDP_123_PACKET, 0), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("ip")?;
+        assert_eq!(
+            filter.image(),
+            [
+                "(000) ldh      [12]",
+                "(001) jeq      #0x800           jt 2\tjf 3",
+                "(002) ret      #65535",
+                "(003) ret      #0",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_recompile() -> Result<(), BpfJitError> {
+        let mut filter = BpfJit::new("udp dst port 123")?;
+        assert!(filter.recompile("tcp port notanumber").is_err());
+        assert_eq!(filter.source(), "udp dst port 123");
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), false);
+
+        filter.recompile("tcp")?;
+        assert_eq!(filter.source(), "tcp");
+        assert_eq!(filter.linktype(), Some(LinkType::Ethernet));
+        assert_eq!(filter.matches(UDP_123_PACKET), false);
+        assert_eq!(filter.matches(TCP_NULL_PACKET), true);
+
+        // the options the filter was compiled with are kept
+        let mut filter = BpfJitBuilder::new()
+            .snaplen(96)
+            .resolve_names(false)
+            .compile("udp dst port 123")?;
+        filter.recompile("udp")?;
+        assert_eq!(filter.filter_len(UDP_123_PACKET), 96);
+        match filter.recompile("host example.com") {
+            Err(BpfJitError::Compile { message, .. }) => {
+                assert!(message.contains("example.com"), "{}", message)
+            }
+            other => panic!("expected a compile error, got {:?}", other),
+        }
+        assert_eq!(filter.source(), "udp");
+        let mut filter = BpfJit::new_with_netmask("udp", LinkType::Ethernet, 0xffffff00)?;
+        filter.recompile("ip broadcast")?;
+
+        let mut filter = BpfJit::accept_all(LinkType::RawIp)?;
+        filter.recompile("udp dst port 123")?;
+        assert_eq!(filter.matches(&UDP_123_PACKET[14..]), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compile_after_panic() -> Result<(), BpfJitError> {
+        let panicked = thread::spawn(|| {
+            let _filter = BpfJit::new("udp dst port 123").unwrap();
+            let _insns = BpfJit::new("udp").unwrap().image();
+            panic!("unrelated panic");
+        })
+        .join();
+        assert!(panicked.is_err());
+
+        let filter = BpfJit::new("udp dst port 123")?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert!(!filter.image().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_bounded() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let negated = filter.negate()?;
+        let combined = filter.or(&BpfJit::new("tcp")?)?;
+        for packet in &[
+            UDP_123_PACKET,
+            TCP_NULL_PACKET,
+            &UDP_123_PACKET[..30],
+            &[][..],
+        ] {
+            for filter in &[&filter, &negated, &combined] {
+                assert_eq!(
+                    filter.matches_bounded(packet, 1000)?,
+                    filter.matches(packet)
+                );
+            }
+        }
+
+        // 100 no-op loads before accepting
+        let mut insns = vec![
+            bpf_insn_t {
+                code: 0x00,
+                jt: 0,
+                jf: 0,
+                k: 0
+            };
+            100
+        ];
+        insns.push(bpf_insn_t {
+            code: 0x06,
+            jt: 0,
+            jf: 0,
+            k: 65535,
+        });
+        let long_filter = BpfJit::from_insns(&insns)?;
+        match long_filter.matches_bounded(UDP_123_PACKET, 10) {
+            Err(BpfJitError::StepLimitExceeded) => {}
+            other => panic!("expected BpfJitError::StepLimitExceeded, got {:?}", other),
+        }
+        assert_eq!(long_filter.matches_bounded(UDP_123_PACKET, 101)?, true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_buffer_types() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let vec = UDP_123_PACKET.to_vec();
+        let mut array = [0u8; 90];
+        array.copy_from_slice(UDP_123_PACKET);
+        let bytes = bytes::Bytes::from_static(UDP_123_PACKET);
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        assert_eq!(filter.matches(&vec), true);
+        assert_eq!(filter.matches(&vec[..]), true);
+        assert_eq!(filter.matches(&array), true);
+        assert_eq!(filter.matches(&bytes), true);
+        assert_eq!(filter.matches_buf(&vec), true);
+        assert_eq!(filter.matches_buf(array), true);
+        assert_eq!(filter.matches_buf(&bytes), true);
+        assert_eq!(filter.matches_buf(bytes), true);
+        assert_eq!(filter.matches_buf(vec), true);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<(), BpfJitError> {
+        let simple_stats = BpfJit::new("ip")?.stats();
+        assert_eq!(
+            simple_stats,
+            ProgramStats {
+                instructions: 4,
+                jumps: 1,
+                packet_loads: 1,
+                memory_accesses: 0,
+                truncates: false,
+                max_branch_depth: 1,
+            }
+        );
+
+        let complex_filter = BpfJit::new("tcp or udp or icmp or arp")?;
+        let complex_stats = complex_filter.stats();
+        assert_eq!(
+            complex_stats.instructions,
+            complex_filter.instruction_count()
+        );
+        assert!(complex_stats.jumps > simple_stats.jumps);
+        assert!(complex_stats.packet_loads > simple_stats.packet_loads);
+        assert!(complex_stats.max_branch_depth > simple_stats.max_branch_depth);
+        assert!(complex_stats.max_branch_depth <= complex_stats.jumps);
+
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x00, jt: 0, jf: 0, k: 7 },  // ld #7
+            bpf_insn_t { code: 0x02, jt: 0, jf: 0, k: 3 },  // st M[3]
+            bpf_insn_t { code: 0x60, jt: 0, jf: 0, k: 3 },  // ld M[3]
+            bpf_insn_t { code: 0x16, jt: 0, jf: 0, k: 0 },  // ret a
+        ];
+        let stats = BpfJit::from_insns(&insns)?.stats();
+        assert_eq!(stats.memory_accesses, 2);
+        assert_eq!(stats.truncates, true);
+        assert_eq!(stats.max_branch_depth, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_ddd() -> Result<(), BpfJitError> {
+        // tcpdump -ddd ip
+        let dump = "4\n40 0 0 12\n21 0 1 2048\n6 0 0 65535\n6 0 0 0\n";
+        let filter = BpfJit::from_ddd(dump)?;
+        assert_eq!(filter.instructions(), BpfJit::new("ip")?.instructions());
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+
+        let dump = filter
+            .instructions()
+            .iter()
+            .map(|insn| format!("{} {} {} {}\n", insn.code, insn.jt, insn.jf, insn.k))
+            .fold(format!("{}\n", filter.instruction_count()), |dump, line| {
+                dump + &line
+            });
+        assert_eq!(BpfJit::from_ddd(&dump)?, filter);
+
+        match BpfJit::from_ddd("3\n40 0 0 12\n21 0 1 2048\n6 0 0 65535\n6 0 0 0\n") {
+            Err(BpfJitError::Parse { line: 1, .. }) => {}
+            other => panic!("expected a parse error on line 1, got {:?}", other),
+        }
+        match BpfJit::from_ddd("2\n40 0 0 12\n6 0 0\n") {
+            Err(BpfJitError::Parse { line: 3, .. }) => {}
+            other => panic!("expected a parse error on line 3, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_equals_bytecode() -> Result<(), BpfJitError> {
+        // tcpdump -dd -s 65535 ip
+        #[rustfmt::skip]
+        let dump = [
+            bpf_insn_t { code: 0x28, jt: 0, jf: 0, k: 0x0000000c },
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 0x00000800 },
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0x0000ffff },
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0x00000000 },
+        ];
+        let filter = BpfJit::new("ip")?;
+        assert_eq!(filter.equals_bytecode(&dump), true);
+        assert_eq!(filter.equals_bytecode(&dump[..3]), false);
+        assert_eq!(BpfJit::new("tcp")?.equals_bytecode(&dump), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_clone() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let cloned = filter.try_clone()?;
+        assert_eq!(cloned, filter);
+        assert_eq!(cloned.is_jit(), true);
+        assert_eq!(cloned.source(), filter.source());
+        assert_eq!(cloned.matches(UDP_123_PACKET), true);
+
+        let interpreted = BpfJitBuilder::new()
+            .jit(false)
+            .compile("udp dst port 123")?;
+        assert_eq!(interpreted.try_clone()?.is_jit(), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_ethernet_vlan() -> Result<(), BpfJitError> {
+        // the same frame, with an 802.1Q tag for VLAN 100 after the MAC addresses
+        let mut tagged = UDP_123_PACKET[..12].to_vec();
+        tagged.extend_from_slice(&[0x81, 0x00, 0x00, 0x64]);
+        tagged.extend_from_slice(&UDP_123_PACKET[12..]);
+
+        let filter = BpfJit::new_ethernet_vlan("ip")?;
+        assert_eq!(filter.source(), "vlan and (ip)");
+        assert_eq!(filter.matches(&tagged[..]), true);
+        assert_eq!(filter.matches(UDP_123_PACKET), false);
+
+        let filter = BpfJit::new("vlan and ip")?;
+        assert_eq!(filter.matches(&tagged[..]), true);
+        assert_eq!(filter.matches(UDP_123_PACKET), false);
+        assert_eq!(BpfJit::new("ip")?.matches(&tagged[..]), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_reject_all() -> Result<(), BpfJitError> {
+        // older libpcap refuses to compile filters that reject everything
+        if let Ok(filter) = BpfJit::new("ip and not ip") {
+            assert_eq!(filter.is_reject_all(), true);
+            assert_eq!(filter.is_match_all(), false);
+        }
+        assert_eq!(
+            BpfJit::reject_all(LinkType::Ethernet)?.is_reject_all(),
+            true
+        );
+        assert_eq!(
+            BpfJit::accept_all(LinkType::Ethernet)?.is_reject_all(),
+            false
+        );
+        assert_eq!(BpfJit::new("udp dst port 123")?.is_reject_all(), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_capi() {
+        unsafe {
+            let mut jit = std::ptr::null_mut();
+            let filter = b"udp dst port 123\0".as_ptr() as *const libc::c_char;
+            assert_eq!(
+                bpfjit_sys_compile(filter, 1, &mut jit),
+                bpfjit_error_t::BPFJIT_OK
+            );
+            assert!(!jit.is_null());
+            assert_eq!(
+                bpfjit_sys_matches(jit, UDP_123_PACKET.as_ptr(), UDP_123_PACKET.len()),
+                1
+            );
+            assert_eq!(
+                bpfjit_sys_matches(jit, TCP_NULL_PACKET.as_ptr(), TCP_NULL_PACKET.len()),
+                0
+            );
+            assert_eq!(bpfjit_sys_matches(jit, std::ptr::null(), 0), 0);
+            bpfjit_sys_free(jit);
+
+            let filter = b"tcp port notanumber\0".as_ptr() as *const libc::c_char;
+            assert_eq!(
+                bpfjit_sys_compile(filter, 1, &mut jit),
+                bpfjit_error_t::BPFJIT_COMPILE_ERR
+            );
+            assert!(jit.is_null());
+            let filter = b"udp\0".as_ptr() as *const libc::c_char;
+            // DLT_IPV4, which has no variant of its own
+            assert_eq!(
+                bpfjit_sys_compile(filter, 228, &mut jit),
+                bpfjit_error_t::BPFJIT_OK
+            );
+            bpfjit_sys_free(jit);
+            assert_eq!(
+                bpfjit_sys_compile(filter, -1, &mut jit),
+                bpfjit_error_t::BPFJIT_UNKNOWN_LINKTYPE
+            );
+            assert_eq!(
+                bpfjit_sys_compile(std::ptr::null(), 1, &mut jit),
+                bpfjit_error_t::BPFJIT_INVALID_ARG
+            );
+            bpfjit_sys_free(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_explain() -> Result<(), BpfJitError> {
+        // udp or tcp
+        #[rustfmt::skip]
+        let insns = [
+            bpf_insn_t { code: 0x30, jt: 0, jf: 0, k: 23 },    // ldb [23]
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 17 },    // jeq #17 jt 2 jf 3
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 65535 }, // ret #65535
+            bpf_insn_t { code: 0x15, jt: 0, jf: 1, k: 6 },     // jeq #6 jt 4 jf 5
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 65535 }, // ret #65535
+            bpf_insn_t { code: 0x06, jt: 0, jf: 0, k: 0 },     // ret #0
+        ];
+        let filter = BpfJit::from_insns(&insns)?;
+
+        let udp = filter.explain(UDP_123_PACKET)?;
+        assert_eq!(udp.steps, vec![0, 1, 2]);
+        assert_eq!(udp.ret, Some(2));
+        assert_eq!(udp.value, 65535);
+        assert_eq!(udp.matched(), true);
+
+        let tcp = filter.explain(TCP_NULL_PACKET)?;
+        assert_eq!(tcp.steps, vec![0, 1, 3, 4]);
+        assert_eq!(tcp.ret, Some(4));
+        assert_eq!(tcp.matched(), true);
+
+        let mut icmp = TCP_NULL_PACKET.to_vec();
+        icmp[23] = 1;
+        let icmp = filter.explain(&icmp)?;
+        assert_eq!(icmp.steps, vec![0, 1, 3, 5]);
+        assert_eq!(icmp.ret, Some(5));
+        assert_eq!(icmp.matched(), false);
+
+        // rejected by the load, rather than by a ret
+        let short = filter.explain(&TCP_NULL_PACKET[..20])?;
+        assert_eq!(short.steps, vec![0]);
+        assert_eq!(short.ret, None);
+        assert_eq!(short.matched(), false);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_skipping() -> Result<(), BpfJitError> {
+        // the IP packets inside outer Ethernet, IPv4 and GRE headers
+        let mut outer = UDP_123_PACKET[..34].to_vec();
+        outer[23] = 47;
+        outer.extend_from_slice(&[0x00, 0x00, 0x08, 0x00]);
+        let udp = [&outer[..], &UDP_123_PACKET[14..]].concat();
+        let tcp = [&outer[..], &TCP_NULL_PACKET[14..]].concat();
+
+        let filter = BpfJit::new_ip("udp dst port 123")?;
+        assert_eq!(filter.matches(&udp), false);
+        assert_eq!(filter.matches_skipping(&udp, outer.len()), true);
+        assert_eq!(filter.matches_skipping(&tcp, outer.len()), false);
+        assert_eq!(filter.matches_skipping(&udp, udp.len()), false);
+        assert_eq!(filter.matches_skipping(UDP_123_PACKET, 0), false);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot skip")]
+    fn test_matches_skipping_past_end() {
+        let filter = BpfJit::new_ip("udp").unwrap();
+        filter.matches_skipping(UDP_123_PACKET, UDP_123_PACKET.len() + 1);
+    }
+
+    #[test]
+    fn test_with_options() -> Result<(), BpfJitError> {
+        let default = BpfJit::with_options("udp dst port 123", CompileOptions::default())?;
+        assert_eq!(default.linktype(), Some(LinkType::Ethernet));
+        assert_eq!(default.matches(UDP_123_PACKET), true);
+        assert_eq!(default.filter_len(UDP_123_PACKET), 65535);
+        assert_eq!(
+            default.instructions(),
+            BpfJit::new("udp dst port 123")?.instructions()
+        );
+
+        let options = CompileOptions {
+            linktype: LinkType::RawIp,
+            snaplen: 64,
+            netmask: 0xffff_ff00,
+            optimize: false,
+        };
+        let custom = BpfJit::with_options("udp dst port 123", options)?;
+        assert_eq!(custom.linktype(), Some(LinkType::RawIp));
+        assert_eq!(custom.matches(&UDP_123_PACKET[14..]), true);
+        assert_eq!(custom.filter_len(&UDP_123_PACKET[14..]), 64);
+        assert_eq!(CompileOptions::from(BpfJitBuilder::from(options)), options);
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_against_snaplen() -> Result<(), BpfJitError> {
+        BpfJit::new("udp")?.validate_against_snaplen()?;
+        BpfJit::new_with_snaplen("udp", LinkType::Ethernet, 24)?.validate_against_snaplen()?;
+
+        // the protocol is at ether[23]
+        let truncated = BpfJit::new_with_snaplen("udp", LinkType::Ethernet, 20)?;
+        match truncated.validate_against_snaplen() {
+            Err(BpfJitError::OffsetBeyondSnaplen {
+                offset,
+                snaplen: 20,
+            }) if offset > 20 => {}
+            other => panic!("expected BpfJitError::OffsetBeyondSnaplen, got {:?}", other),
+        }
+        truncated
+            .try_clone()?
+            .validate_against_snaplen()
+            .unwrap_err();
+        // which still accepts non-IP packets, having only loaded the EtherType
+        truncated.negate()?.validate_against_snaplen()?;
+
+        // only one of the alternatives has to fit
+        let deep = BpfJit::new_with_snaplen("ether[100] = 1", LinkType::Ethernet, 64)?;
+        match deep.validate_against_snaplen() {
+            Err(BpfJitError::OffsetBeyondSnaplen {
+                offset: 101,
+                snaplen: 64,
+            }) => {}
+            other => panic!("expected BpfJitError::OffsetBeyondSnaplen, got {:?}", other),
+        }
+        let either = BpfJit::new_with_snaplen("udp or ether[100] = 1", LinkType::Ethernet, 64)?;
+        either.validate_against_snaplen()?;
+        assert_eq!(either.matches(&UDP_123_PACKET[..64]), true);
+
+        // programs built from instructions have no snaplen to compare against
+        BpfJit::from_insns(truncated.instructions())?.validate_against_snaplen()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disassemble_into() -> Result<(), BpfJitError> {
+        let filter = BpfJit::accept_all(LinkType::Ethernet)?;
+        let mut buf = String::from("filter:\n");
+        filter.disassemble_into(&mut buf);
+        assert_eq!(buf, format!("filter:\n{}", filter.disassemble()));
+
+        let capacity = buf.capacity();
+        buf.clear();
+        filter.disassemble_into(&mut buf);
+        assert_eq!(buf, filter.disassemble());
+        assert_eq!(buf.capacity(), capacity);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_to_sock_fprog() -> Result<(), BpfJitError> {
+        let filter = BpfJit::new("udp dst port 123")?;
+        let prog = filter.to_sock_fprog()?;
+        assert_eq!(prog.len() as usize, filter.instruction_count());
+        assert_eq!(prog.filter(), &filter.to_sock_filter()[..]);
+
+        // the pointers stay valid after the struct moves
+        let moved = Box::new(prog);
+        unsafe {
+            let fprog = &*moved.as_ptr();
+            assert_eq!(fprog.len, moved.len());
+            assert_eq!(fprog.filter as *const SockFilter, moved.filter().as_ptr());
+            assert_eq!((*fprog.filter).code, filter.instructions()[0].code);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_names() -> Result<(), BpfJitError> {
+        let builder = BpfJitBuilder::new().resolve_names(false);
+        for (filter, name) in &[
+            ("host example.com", "example.com"),
+            ("tcp and dst host localhost", "localhost"),
+            ("src gateway router", "router"),
+            ("not (net corp)", "corp"),
+            ("ip and dst example.com", "example.com"),
+            ("example.com", "example.com"),
+            ("host 10.0.0.1 or example.com", "example.com"),
+            ("host helios and (hot or ace)", "helios"),
+            ("host 10.0.0.1 and (hot or ace)", "hot"),
+            ("host 10.0.0.1 and (10.0.0.2 or ace)", "ace"),
+            ("tcp port http or src ace", "ace"),
+        ] {
+            match builder.compile(filter) {
+                Err(BpfJitError::Compile { message, offset }) => {
+                    assert!(message.contains(name), "{}", message);
+                    assert_eq!(offset, filter.find(name));
+                }
+                other => panic!("expected BpfJitError::Compile, got {:?}", other.err()),
+            }
+        }
+
+        // addresses, ports and protocols
+        let filter = builder.compile("udp dst port 123")?;
+        assert_eq!(filter.matches(UDP_123_PACKET), true);
+        for filter in &[
+            "host 192.168.0.1",
+            "ether src aa:bb:cc:dd:ee:ff",
+            "ip6 dst fe80::1",
+            "src net 10.0.0.0/8 and dst port 53",
+            "tcp port http",
+            "tcp port http or https",
+            "udp port ftp-data or domain",
+            "tcp port 80 and not ssh",
+            "tcp portrange 1000-2000 or udp port ftp-data",
+            "ip proto \\tcp and ether proto arp",
+            "tcp[tcpflags] & tcp-syn != 0 and len - 14 > 0x40",
+            "wlan type mgt subtype beacon",
+            "ether host aabb.ccdd.eeff or ether dst 00-11-22-33-44-55",
+        ] {
+            if let Err(BpfJitError::Compile { message, .. }) = builder.compile(filter) {
+                assert!(!message.contains("name resolution"), "{}", message);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_netmask_addr() -> Result<(), BpfJitError> {
+        let mask = std::net::Ipv4Addr::new(255, 255, 255, 0);
+        let builder = BpfJitBuilder::new().netmask_addr(mask);
+        assert_eq!(builder, BpfJitBuilder::new().netmask(0xffffff00));
+
+        let filter = builder.compile("ip broadcast")?;
+        let mut broadcast = UDP_123_PACKET.to_vec();
+        broadcast[33] = 0xff;
+        assert_eq!(filter.matches(&broadcast), true);
+        assert_eq!(filter.matches(UDP_123_PACKET), false);
+        Ok(())
+    }
 }